@@ -0,0 +1,302 @@
+use crate::exec::Stream;
+use crate::lines::LineSplitter;
+use regex::Regex;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+static SPILL_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// How much of the start and of the end of a stream is kept.
+#[derive(Clone, Copy)]
+pub enum Limit {
+    Bytes(usize),
+    Lines(usize),
+}
+
+impl Limit {
+    /// Parse a positive number of bytes with an optional `k`, `M` or `G`
+    /// suffix, like `64k`, or of lines with an `l` suffix, like `200l`.
+    pub fn parse(text: &str) -> Option<Limit> {
+        let number_len = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let number: usize = text[..number_len].parse().ok().filter(|&n| n > 0)?;
+        let size = |unit: usize| number.checked_mul(unit).map(Limit::Bytes);
+        match &text[number_len..] {
+            "" => size(1),
+            "k" | "K" => size(1 << 10),
+            "M" => size(1 << 20),
+            "G" => size(1 << 30),
+            "l" => Some(Limit::Lines(number)),
+            _ => None,
+        }
+    }
+}
+
+/// How the captured streams are bounded.
+pub struct Bounds {
+    pub limit: Limit,
+    /// Whether to write the full streams whose output is dropped to
+    /// temporary files.
+    pub spill: bool,
+    /// The regexes to look for in the output. It cannot be searched once
+    /// dropped, so they are matched line by line as it is read.
+    pub patterns: Vec<(Stream, Regex)>,
+}
+
+/// What was kept of a stream: its chunks and when they were read, and the
+/// patterns matched in the whole stream.
+pub struct Captured {
+    pub chunks: Vec<(Instant, Vec<u8>)>,
+    pub matches: Vec<String>,
+}
+
+/// The output of a stream being read, keeping only its start and its end
+/// when it is bounded.
+pub struct Capture {
+    stream: Stream,
+    bounds: Option<Arc<Bounds>>,
+    head: Vec<(Instant, Vec<u8>)>,
+    /// The bytes or lines in `head`.
+    head_len: usize,
+    head_full: bool,
+    tail: VecDeque<(Instant, Vec<u8>)>,
+    /// The bytes or newlines in `tail`.
+    tail_len: usize,
+    dropped_bytes: u64,
+    dropped_lines: u64,
+    dropped_at: Option<Instant>,
+    /// The lines being read, to match the patterns against.
+    lines: LineSplitter<()>,
+    matched: Vec<bool>,
+    spill: Option<(File, PathBuf)>,
+}
+
+fn count_lines(data: &[u8]) -> usize {
+    data.iter().filter(|&&byte| byte == b'\n').count()
+}
+
+/// Create the file the full `stream` is written to, readable by the user
+/// only. A file already there, possibly planted by another user, is never
+/// opened.
+fn create_spill(stream: Stream) -> Option<(File, PathBuf)> {
+    loop {
+        let path = env::temp_dir().join(format!(
+            "nrbt-{}-{}.{}",
+            process::id(),
+            SPILL_ID.fetch_add(1, Ordering::SeqCst),
+            stream.name()
+        ));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => return Some((file, path)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Note the patterns of `bounds` that a line of `stream` matches.
+fn match_line(bounds: &Option<Arc<Bounds>>, stream: Stream, matched: &mut [bool], line: &[u8]) {
+    if let Some(bounds) = bounds {
+        let line = String::from_utf8_lossy(line);
+        for (i, (pattern_stream, regex)) in bounds.patterns.iter().enumerate() {
+            if !matched[i] && *pattern_stream == stream && regex.is_match(&line) {
+                matched[i] = true;
+            }
+        }
+    }
+}
+
+/// The patterns of `bounds` that a line of `data` matches, for output of
+/// `stream` that nrbt wrote itself rather than read from a pipe.
+pub fn match_output(bounds: &Option<Arc<Bounds>>, stream: Stream, data: &[u8]) -> Vec<String> {
+    let patterns = match bounds {
+        Some(bounds) => &bounds.patterns,
+        None => return Vec::new(),
+    };
+    let mut matched = vec![false; patterns.len()];
+    let mut lines = LineSplitter::new();
+    lines.push((), data, |_, line| {
+        match_line(bounds, stream, &mut matched, line)
+    });
+    lines.finish(|_, line| match_line(bounds, stream, &mut matched, line));
+    patterns
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| *matched)
+        .map(|((_, regex), _)| regex.as_str().to_string())
+        .collect()
+}
+
+impl Capture {
+    pub fn new(stream: Stream, bounds: Option<Arc<Bounds>>) -> Capture {
+        let spill = match &bounds {
+            Some(bounds) if bounds.spill => create_spill(stream),
+            _ => None,
+        };
+        let patterns = bounds.as_ref().map_or(0, |bounds| bounds.patterns.len());
+        Capture {
+            stream,
+            bounds,
+            head: Vec::new(),
+            head_len: 0,
+            head_full: false,
+            tail: VecDeque::new(),
+            tail_len: 0,
+            dropped_bytes: 0,
+            dropped_lines: 0,
+            dropped_at: None,
+            lines: LineSplitter::new(),
+            matched: vec![false; patterns],
+            spill,
+        }
+    }
+
+    pub fn push(&mut self, time: Instant, data: &[u8]) {
+        let limit = match &self.bounds {
+            Some(bounds) => bounds.limit,
+            None => {
+                self.head.push((time, data.to_vec()));
+                return;
+            }
+        };
+
+        if let Some((file, _)) = &mut self.spill {
+            if file.write_all(data).is_err() {
+                self.spill = None;
+            }
+        }
+        let (bounds, stream, matched) = (&self.bounds, self.stream, &mut self.matched);
+        self.lines.push((), data, |_, line| {
+            match_line(bounds, stream, matched, line)
+        });
+
+        let mut rest = data;
+        if !self.head_full {
+            let (kept, full) = match limit {
+                Limit::Bytes(max) => {
+                    let kept = rest.len().min(max - self.head_len);
+                    self.head_len += kept;
+                    (kept, self.head_len == max)
+                }
+                Limit::Lines(max) => {
+                    let mut kept = rest.len();
+                    for (i, _) in rest.iter().enumerate().filter(|(_, &byte)| byte == b'\n') {
+                        self.head_len += 1;
+                        if self.head_len == max {
+                            kept = i + 1;
+                            break;
+                        }
+                    }
+                    (kept, self.head_len == max)
+                }
+            };
+            if kept > 0 {
+                self.head.push((time, rest[..kept].to_vec()));
+            }
+            self.head_full = full;
+            rest = &rest[kept..];
+        }
+        if rest.is_empty() {
+            return;
+        }
+
+        self.tail_len += match limit {
+            Limit::Bytes(_) => rest.len(),
+            Limit::Lines(_) => count_lines(rest),
+        };
+        self.tail.push_back((time, rest.to_vec()));
+        match limit {
+            Limit::Bytes(max) => {
+                while self.tail_len > max {
+                    let excess = self.tail_len - max;
+                    self.drop_front(excess);
+                }
+            }
+            Limit::Lines(max) => {
+                while self.tail_len > max {
+                    let (_, front) = &self.tail[0];
+                    let len = match front.iter().position(|&byte| byte == b'\n') {
+                        Some(i) => i + 1,
+                        None => front.len(),
+                    };
+                    self.drop_front(len);
+                }
+            }
+        }
+    }
+
+    /// Drop up to `len` bytes from the start of the tail, within its first
+    /// chunk.
+    fn drop_front(&mut self, len: usize) {
+        let (time, front) = self.tail.pop_front().unwrap();
+        let len = len.min(front.len());
+        let dropped_lines = count_lines(&front[..len]);
+        self.dropped_at.get_or_insert(time);
+        self.dropped_bytes += len as u64;
+        self.dropped_lines += dropped_lines as u64;
+        self.tail_len -= match self.bounds.as_ref().map(|bounds| bounds.limit) {
+            Some(Limit::Lines(_)) => dropped_lines,
+            _ => len,
+        };
+        if len < front.len() {
+            self.tail.push_front((time, front[len..].to_vec()));
+        }
+    }
+
+    /// The output kept, with a line telling what was dropped where it was.
+    pub fn finish(mut self) -> Captured {
+        let (bounds, stream, matched) = (&self.bounds, self.stream, &mut self.matched);
+        self.lines
+            .finish(|_, line| match_line(bounds, stream, matched, line));
+        let matches = match &self.bounds {
+            Some(bounds) => bounds
+                .patterns
+                .iter()
+                .zip(&self.matched)
+                .filter(|(_, &matched)| matched)
+                .map(|((_, regex), _)| regex.as_str().to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut chunks = self.head;
+        if let Some(time) = self.dropped_at {
+            let mut note = String::new();
+            if chunks
+                .last()
+                .is_some_and(|(_, data)| !data.ends_with(b"\n"))
+            {
+                note.push('\n');
+            }
+            note.push_str(&format!(
+                "[nrbt: {} bytes, {} lines dropped",
+                self.dropped_bytes, self.dropped_lines
+            ));
+            if let Some((_, path)) = &self.spill {
+                note.push_str(&format!(", full output in {}", path.display()));
+            }
+            note.push_str("]\n");
+            chunks.push((time, note.into_bytes()));
+        } else if let Some((_, path)) = &self.spill {
+            // Nothing was dropped, the report has it all.
+            let _ = fs::remove_file(path);
+        }
+        chunks.extend(self.tail);
+
+        Captured { chunks, matches }
+    }
+}
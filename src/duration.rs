@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+/// Parse a duration made of numbers followed by a unit among `ms`, `s`, `m`,
+/// `h` and `d`, like `90`, `1.5s` or `1h30m`. A number without unit is a
+/// number of seconds.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let seconds = match &rest[..unit_len] {
+            "" | "s" => 1.0,
+            "ms" => 0.001,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total += number * seconds;
+    }
+
+    Duration::try_from_secs_f64(total).ok()
+}
+
+/// Format a duration the way `parse_duration` reads it, e.g. `1h30m` or
+/// `2.5s`.
+pub fn format_duration(duration: Duration) -> String {
+    let mut secs = duration.as_secs();
+    let millis = duration.subsec_millis();
+    let mut text = String::new();
+    for &(unit, len) in &[("d", 86400), ("h", 3600), ("m", 60)] {
+        if secs >= len {
+            text.push_str(&format!("{}{}", secs / len, unit));
+            secs %= len;
+        }
+    }
+    if millis > 0 {
+        let fraction = format!("{:03}", millis);
+        text.push_str(&format!("{}.{}s", secs, fraction.trim_end_matches('0')));
+    } else if secs > 0 || text.is_empty() {
+        text.push_str(&format!("{}s", secs));
+    }
+    text
+}
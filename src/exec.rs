@@ -0,0 +1,972 @@
+use crate::capture::{self, Bounds, Capture, Captured, Limit};
+use crate::expand::{expand_string, expand_word, expand_words, Context};
+use crate::live_log::{LineWriter, LiveLog};
+use crate::parser::{Cmd, Node, Redirect, RedirectKind, Stage};
+use crate::pgroup::{self, Leftover};
+use crate::signals;
+use crate::watchdog::{TimedOut, Watchdog};
+use regex::Regex;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub struct CmdReturn {
+    pub status: Option<i32>,
+    pub signal: Option<i32>,
+    pub stderr: Vec<u8>,
+    pub stdout: Vec<u8>,
+    pub steps: Vec<StepReturn>,
+    pub timed_out: Option<TimedOut>,
+    /// The signal nrbt received and forwarded, which interrupted the run.
+    pub interrupted: Option<i32>,
+    /// Processes left running by the pipelines, when looked for.
+    pub leftovers: Vec<Leftover>,
+    /// Whether the output was bounded, so that `stdout` and `stderr` may
+    /// miss part of it.
+    pub bounded: bool,
+    /// The regexes matched in the output when it was bounded.
+    pub matches: Vec<(Stream, String)>,
+}
+
+pub struct StepReturn {
+    pub argv: Vec<String>,
+    pub status: Option<i32>,
+    pub signal: Option<i32>,
+    pub duration: Duration,
+    pub stderr: Vec<u8>,
+    pub stdout: Vec<u8>,
+    /// The captured output, in the order it was read.
+    pub chunks: Vec<Chunk>,
+    /// Where stdout went, if it was not captured as stdout.
+    pub stdout_redirect: Option<String>,
+    /// Where stderr went, if it was not captured as stderr.
+    pub stderr_redirect: Option<String>,
+    /// Whether the step ran as part of a command substitution.
+    pub substitution: bool,
+}
+
+impl CmdReturn {
+    fn new() -> CmdReturn {
+        CmdReturn {
+            status: None,
+            signal: None,
+            stderr: [].to_vec(),
+            stdout: [].to_vec(),
+            steps: Vec::new(),
+            timed_out: None,
+            interrupted: None,
+            leftovers: Vec::new(),
+            bounded: false,
+            matches: Vec::new(),
+        }
+    }
+
+    /// Whether `regex` matches the output of the run on `stream`. When it was
+    /// bounded, regexes were matched line by line as it was read instead.
+    pub fn output_matches(&self, stream: Stream, regex: &Regex) -> bool {
+        if self.bounded {
+            return self
+                .matches
+                .iter()
+                .any(|(matched, pattern)| *matched == stream && pattern == regex.as_str());
+        }
+        let output = match stream {
+            Stream::Stdout => &self.stdout,
+            Stream::Stderr => &self.stderr,
+        };
+        regex.is_match(&String::from_utf8_lossy(output))
+    }
+}
+
+impl StepReturn {
+    fn succeeded(&self) -> bool {
+        self.status == Some(0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// A piece of captured output, as it was read while the step ran.
+pub struct Chunk {
+    pub stream: Stream,
+    /// When it was read, from the start of the step.
+    pub offset: Duration,
+    pub data: Vec<u8>,
+}
+
+/// What a standard file descriptor of a pipeline stage is connected to.
+#[derive(Clone)]
+enum Io {
+    Inherit,
+    PreviousStage,
+    NextStage,
+    Capture(Stream),
+    File(Rc<File>, String),
+}
+
+impl Io {
+    fn same_as(&self, other: &Io) -> bool {
+        match (self, other) {
+            (Io::Inherit, Io::Inherit)
+            | (Io::PreviousStage, Io::PreviousStage)
+            | (Io::NextStage, Io::NextStage) => true,
+            (Io::Capture(stream), Io::Capture(other)) => stream == other,
+            (Io::File(file, _), Io::File(other, _)) => Rc::ptr_eq(file, other),
+            _ => false,
+        }
+    }
+
+    /// Describe where an output stream went when it was not captured as is.
+    fn describe(&self) -> String {
+        match self {
+            Io::Inherit => "redirected to nrbt's stdin, not captured".to_string(),
+            Io::PreviousStage => "redirected to the previous command, not captured".to_string(),
+            Io::NextStage => "piped to the next command".to_string(),
+            Io::Capture(Stream::Stdout) => "redirected to stdout".to_string(),
+            Io::Capture(Stream::Stderr) => "redirected to stderr".to_string(),
+            Io::File(_, path) => format!("redirected to {}, not captured", path),
+        }
+    }
+}
+
+/// What commands run with. A subshell works on a copy of the scope of its
+/// parent, so that `cd` and assignments do not outlive it.
+#[derive(Clone)]
+struct Scope {
+    cwd: PathBuf,
+    /// Variables assigned on the command line, on top of the environment.
+    /// Like in a shell, only those already in the environment are exported
+    /// to the commands.
+    vars: HashMap<String, String>,
+    /// The standard file descriptors, as redirected by the enclosing groups.
+    io: [Io; 3],
+}
+
+impl Scope {
+    fn new() -> Scope {
+        Scope {
+            cwd: env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            vars: HashMap::new(),
+            io: [
+                Io::Inherit,
+                Io::Capture(Stream::Stdout),
+                Io::Capture(Stream::Stderr),
+            ],
+        }
+    }
+}
+
+/// A pipeline stage that was successfully spawned, along with the threads
+/// draining its captured streams.
+struct Spawned {
+    child: Child,
+    readers: Vec<(Stream, Reader)>,
+    stdout_redirect: Option<String>,
+    stderr_redirect: Option<String>,
+}
+
+/// A thread reading a captured stream.
+type Reader = JoinHandle<io::Result<Captured>>;
+
+/// How a captured stream is read.
+#[derive(Clone, Default)]
+struct Reading {
+    /// Whether to copy it to the same stream of nrbt as it arrives.
+    tee: bool,
+    bounds: Option<Arc<Bounds>>,
+    log: Option<Arc<LiveLog>>,
+    /// Set to stop reading before the end, once what is already written was
+    /// read.
+    stop: Option<Arc<AtomicBool>>,
+}
+
+/// How long a reader told to stop goes on reading output that keeps coming.
+const DRAIN_GRACE: Duration = Duration::from_millis(200);
+
+/// Copy a chunk of output to the same stream of nrbt. Errors are ignored, as
+/// they must not stop the capture.
+fn tee_chunk(stream: Stream, chunk: &[u8]) {
+    let _ = match stream {
+        Stream::Stdout => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(chunk).and_then(|_| stdout.flush())
+        }
+        Stream::Stderr => io::stderr().lock().write_all(chunk),
+    };
+}
+
+/// Whether `fd` can be read within `timeout`.
+fn readable(fd: RawFd, timeout: Duration) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    match unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) } {
+        -1 => {
+            let error = io::Error::last_os_error();
+            match error.kind() {
+                ErrorKind::Interrupted => Ok(false),
+                _ => Err(error),
+            }
+        }
+        ready => Ok(ready > 0),
+    }
+}
+
+/// Read a pipe captured as `stream` until its end, noting when each chunk of
+/// output was read. Once told to stop, it ends as soon as no output comes,
+/// or after `DRAIN_GRACE`.
+fn read_all<R>(mut pipe: R, stream: Stream, reading: Reading) -> Reader
+where
+    R: Read + AsRawFd + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut capture = Capture::new(stream, reading.bounds);
+        let mut log = reading.log.map(|log| LineWriter::new(log, stream));
+        let mut buf = [0; 8192];
+        let mut stopped_at = None;
+        loop {
+            // When it may be told to stop, the pipe is polled rather than
+            // read until its end, which does not come while processes left
+            // behind hold it open.
+            let ended = match &reading.stop {
+                Some(stop) => {
+                    if stopped_at.is_none() && stop.load(Ordering::SeqCst) {
+                        stopped_at = Some(Instant::now());
+                    }
+                    let ready = readable(pipe.as_raw_fd(), Duration::from_millis(50))?;
+                    match stopped_at {
+                        Some(stopped_at) if !ready || stopped_at.elapsed() >= DRAIN_GRACE => true,
+                        _ if !ready => continue,
+                        _ => false,
+                    }
+                }
+                None => false,
+            };
+            let read = if ended { Ok(0) } else { pipe.read(&mut buf) };
+            match read {
+                Ok(0) => {
+                    if let Some(log) = log {
+                        log.finish();
+                    }
+                    return Ok(capture.finish());
+                }
+                Ok(len) => {
+                    let time = Instant::now();
+                    if reading.tee {
+                        tee_chunk(stream, &buf[..len]);
+                    }
+                    if let Some(log) = &mut log {
+                        log.push(time, &buf[..len]);
+                    }
+                    capture.push(time, &buf[..len]);
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+    })
+}
+
+fn join_output(handle: Reader) -> io::Result<Captured> {
+    handle
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("output reader panicked")))
+}
+
+fn push_step(cmd_return: &mut CmdReturn, step: StepReturn) {
+    cmd_return.stderr.extend_from_slice(&step.stderr);
+    cmd_return.stdout.extend_from_slice(&step.stdout);
+    cmd_return.steps.push(step);
+}
+
+fn exited_step(argv: &[String], start: Instant, status: ExitStatus) -> StepReturn {
+    StepReturn {
+        argv: argv.to_vec(),
+        status: status.code(),
+        signal: status.signal(),
+        duration: start.elapsed(),
+        stderr: Vec::new(),
+        stdout: Vec::new(),
+        chunks: Vec::new(),
+        stdout_redirect: None,
+        stderr_redirect: None,
+        substitution: false,
+    }
+}
+
+/// A step that failed before its command could be run.
+fn error_step(argv: &[String], start: Instant, status: i32, error_line: String) -> StepReturn {
+    let duration = start.elapsed();
+    let chunks = if error_line.is_empty() {
+        Vec::new()
+    } else {
+        vec![Chunk {
+            stream: Stream::Stderr,
+            offset: duration,
+            data: error_line.clone().into_bytes(),
+        }]
+    };
+    StepReturn {
+        argv: argv.to_vec(),
+        status: Some(status),
+        signal: None,
+        duration,
+        stderr: error_line.into_bytes(),
+        stdout: Vec::new(),
+        chunks,
+        stdout_redirect: None,
+        stderr_redirect: None,
+        substitution: false,
+    }
+}
+
+/// The step of a command that could not be started, failing like a shell
+/// would: with 127 when it was not found, and 126 for any other error, like a
+/// script without a shebang.
+fn failed_step(argv: &[String], start: Instant, error: io::Error) -> StepReturn {
+    let (status, error_line) = match error.kind() {
+        ErrorKind::NotFound => (127, format!("nrbt: command not found: {}", argv[0])),
+        ErrorKind::PermissionDenied => (126, format!("nrbt: permission denied: {}", argv[0])),
+        _ => (126, format!("nrbt: {}: {}", argv[0], error)),
+    };
+    error_step(argv, start, status, error_line)
+}
+
+/// Spawn one stage of a pipeline. `stdin` is the output of the previous stage
+/// and the returned `Stdio` is the one to feed to the next stage, if any. The
+/// stage joins the process group `pgid`, or leads a new one when 0.
+fn spawn_stage(
+    argv: &[String],
+    env: &[(String, String)],
+    scope: &Scope,
+    io: &[Io; 3],
+    stdin: Option<Stdio>,
+    pgid: i32,
+    reading: &dyn Fn(Stream) -> Reading,
+) -> io::Result<(Spawned, Option<Stdio>)> {
+    let mut command = Command::new(&argv[0]);
+    command
+        .process_group(pgid)
+        .args(&argv[1..])
+        .current_dir(&scope.cwd)
+        .envs(
+            scope
+                .vars
+                .iter()
+                .filter(|(name, _)| env::var_os(name).is_some()),
+        )
+        .envs(env.iter().cloned());
+    let mut stdin = stdin;
+    let mut dups = Vec::new();
+
+    for (fd, target) in io.iter().enumerate() {
+        // A descriptor sharing its target with a lower one is duplicated from
+        // it once the child has been set up.
+        if let Some(src) = io[..fd].iter().position(|other| other.same_as(target)) {
+            dups.push((src as i32, fd as i32));
+            continue;
+        }
+        let stdio = match target {
+            Io::Inherit => Stdio::inherit(),
+            Io::PreviousStage => stdin.take().unwrap_or_else(Stdio::null),
+            Io::NextStage | Io::Capture(_) => Stdio::piped(),
+            Io::File(file, _) => Stdio::from(file.try_clone()?),
+        };
+        match fd {
+            0 => command.stdin(stdio),
+            1 => command.stdout(stdio),
+            _ => command.stderr(stdio),
+        };
+    }
+
+    if !dups.is_empty() {
+        unsafe {
+            command.pre_exec(move || {
+                for &(src, dst) in &dups {
+                    if libc::dup2(src, dst) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    let mut child = command.spawn()?;
+    let mut readers = Vec::new();
+    let mut next_stdin = None;
+    if let Some(stdout) = child.stdout.take() {
+        match io[1] {
+            Io::Capture(stream) => {
+                readers.push((stream, read_all(stdout, stream, reading(stream))))
+            }
+            _ => next_stdin = Some(Stdio::from(stdout)),
+        }
+    }
+    if let Some(stderr) = child.stderr.take() {
+        match io[2] {
+            Io::Capture(stream) => {
+                readers.push((stream, read_all(stderr, stream, reading(stream))))
+            }
+            _ => next_stdin = Some(Stdio::from(stderr)),
+        }
+    }
+
+    let redirect = |fd: usize, stream: Stream| match &io[fd] {
+        Io::Capture(captured) if *captured == stream => None,
+        target => Some(target.describe()),
+    };
+    let stage = Spawned {
+        child,
+        readers,
+        stdout_redirect: redirect(1, Stream::Stdout),
+        stderr_redirect: redirect(2, Stream::Stderr),
+    };
+
+    Ok((stage, next_stdin))
+}
+
+fn succeeded(cmd_return: &CmdReturn) -> bool {
+    cmd_return.status == Some(0)
+}
+
+fn negate_status(cmd_return: &mut CmdReturn) {
+    cmd_return.status = if succeeded(cmd_return) {
+        Some(1)
+    } else {
+        Some(0)
+    };
+    cmd_return.signal = None;
+}
+
+struct Executor {
+    cmd_return: CmdReturn,
+    pipefail: bool,
+    leftovers: Leftovers,
+    kill_after: Duration,
+    scope: Scope,
+    /// The status of the last command substitution, which is the one of a
+    /// command only made of assignments.
+    subst_status: Option<(Option<i32>, Option<i32>)>,
+    watchdog: Option<Watchdog>,
+    /// Whether to copy the captured output to nrbt's as it arrives.
+    tee: bool,
+    bounds: Option<Arc<Bounds>>,
+    log: Option<Arc<LiveLog>>,
+    /// Whether a command substitution is being run, whose stdout is not
+    /// output of the run.
+    substituting: bool,
+    /// Whether nrbt runs in the foreground of a terminal, which it then
+    /// hands over to the pipeline being run.
+    terminal: bool,
+}
+
+impl Context for Executor {
+    fn var(&self, name: &str) -> Option<String> {
+        match self.scope.vars.get(name) {
+            Some(value) => Some(value.clone()),
+            None => env::var_os(name).map(|value| value.to_string_lossy().into_owned()),
+        }
+    }
+
+    fn cwd(&self) -> &Path {
+        &self.scope.cwd
+    }
+
+    /// Run the commands of a command substitution and return their stdout.
+    /// Their steps are added to the report, but only their stderr counts as
+    /// output of the whole run. Like a subshell, they cannot change the
+    /// scope of the command they are part of.
+    fn substitute(&mut self, node: &Node) -> Result<String, io::Error> {
+        let outer = mem::replace(&mut self.cmd_return, CmdReturn::new());
+        let scope = self.scope.clone();
+        self.scope.io[1] = Io::Capture(Stream::Stdout);
+        let substituting = mem::replace(&mut self.substituting, true);
+        let result = self.run_node(node);
+        self.substituting = substituting;
+        self.scope = scope;
+        let inner = mem::replace(&mut self.cmd_return, outer);
+        result?;
+
+        self.subst_status = Some((inner.status, inner.signal));
+        self.cmd_return.matches.extend(inner.matches);
+        for mut step in inner.steps {
+            step.substitution = true;
+            self.cmd_return.stderr.extend_from_slice(&step.stderr);
+            self.cmd_return.steps.push(step);
+        }
+        Ok(String::from_utf8_lossy(&inner.stdout).into_owned())
+    }
+}
+
+impl Executor {
+    /// How a stream captured by a stage is read. The stdout of a command
+    /// substitution is its value rather than output of the run, so it is
+    /// neither copied, bounded nor logged.
+    fn reading(&self, stream: Stream) -> Reading {
+        if self.substituting && stream == Stream::Stdout {
+            return Reading::default();
+        }
+        Reading {
+            tee: self.tee,
+            bounds: self.bounds.clone(),
+            log: self.log.clone(),
+            stop: None,
+        }
+    }
+
+    /// Note the patterns matched by the error nrbt wrote for a step that
+    /// failed before its command could be run. No reader captured it, so it
+    /// is only matched here when the output is bounded.
+    fn match_error(&mut self, step: &StepReturn) {
+        let matches = capture::match_output(&self.bounds, Stream::Stderr, &step.stderr);
+        self.cmd_return
+            .matches
+            .extend(matches.into_iter().map(|regex| (Stream::Stderr, regex)));
+    }
+
+    fn timed_out(&self) -> bool {
+        self.watchdog.as_ref().is_some_and(Watchdog::timed_out)
+    }
+
+    /// Whether the run has to stop, because it timed out or nrbt received a
+    /// signal.
+    fn stopped(&self) -> bool {
+        self.timed_out() || signals::received().is_some()
+    }
+
+    /// Make `pgid` the process group to signal on timeout or when nrbt
+    /// receives a signal. A pipeline started after that happened is signaled
+    /// right away. When run from a terminal, the group is also put in its
+    /// foreground, or nrbt back when `pgid` is 0.
+    fn enter_group(&self, pgid: i32) {
+        pgroup::set_current_group(pgid);
+        if self.terminal {
+            pgroup::set_foreground(pgid);
+            if pgid != 0 {
+                // The first stage may have been stopped for reading the
+                // terminal before it was given it.
+                unsafe {
+                    libc::kill(-pgid, libc::SIGCONT);
+                }
+            }
+        }
+        if let Some(signal) = signals::received() {
+            pgroup::signal_current_group(signal);
+        } else if self.timed_out() {
+            pgroup::signal_current_group(libc::SIGTERM);
+        }
+    }
+
+    fn run_node(&mut self, node: &Node) -> Result<(), io::Error> {
+        match node {
+            Node::List(nodes) => {
+                for node in nodes {
+                    self.run_node(node)?;
+                }
+            }
+            Node::And(left, right) => {
+                self.run_node(left)?;
+                if succeeded(&self.cmd_return) {
+                    self.run_node(right)?;
+                }
+            }
+            Node::Or(left, right) => {
+                self.run_node(left)?;
+                if !succeeded(&self.cmd_return) {
+                    self.run_node(right)?;
+                }
+            }
+            Node::Pipeline(pipeline) => {
+                self.run_pipeline(&pipeline.stages)?;
+                if pipeline.negate {
+                    negate_status(&mut self.cmd_return);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a `( ... )` subshell or a `{ ...; }` group, with its redirections
+    /// applying to every command it contains.
+    fn run_group(
+        &mut self,
+        stage: &Stage,
+        body: &Node,
+        redirects: &[Redirect],
+        subshell: bool,
+    ) -> Result<(), io::Error> {
+        let start = Instant::now();
+        let io = match self.setup_io(redirects, self.scope.io.clone())? {
+            Ok(io) => io,
+            Err(error_line) => {
+                let step = error_step(&[stage.to_string()], start, 1, error_line);
+                self.match_error(&step);
+                self.cmd_return.status = step.status;
+                self.cmd_return.signal = None;
+                push_step(&mut self.cmd_return, step);
+                return Ok(());
+            }
+        };
+
+        let outer = mem::replace(&mut self.scope.io, io);
+        let scope = if subshell {
+            Some(self.scope.clone())
+        } else {
+            None
+        };
+        let result = self.run_node(body);
+        if let Some(scope) = scope {
+            self.scope = scope;
+        }
+        self.scope.io = outer;
+        result
+    }
+
+    /// The `cd` builtin, changing the working directory of the current scope.
+    fn change_dir(&mut self, argv: &[String], start: Instant) -> StepReturn {
+        let dir = match (argv.get(1), self.var("HOME")) {
+            _ if argv.len() > 2 => {
+                let error_line = "nrbt: cd: too many arguments".to_string();
+                return error_step(argv, start, 1, error_line);
+            }
+            (Some(dir), _) => dir.clone(),
+            (None, Some(home)) => home,
+            (None, None) => {
+                return error_step(argv, start, 1, "nrbt: cd: HOME not set".to_string());
+            }
+        };
+
+        let error_line = match fs::metadata(self.scope.cwd.join(&dir)) {
+            Ok(metadata) if !metadata.is_dir() => format!("nrbt: cd: {}: Not a directory", dir),
+            Ok(_) => match self.scope.cwd.join(&dir).canonicalize() {
+                Ok(cwd) => {
+                    let pwd = cwd.to_string_lossy().into_owned();
+                    self.scope.vars.insert("PWD".to_string(), pwd);
+                    self.scope.cwd = cwd;
+                    return error_step(argv, start, 0, String::new());
+                }
+                Err(error) => format!("nrbt: cd: {}: {}", dir, error),
+            },
+            Err(error) => format!("nrbt: cd: {}: {}", dir, error),
+        };
+        error_step(argv, start, 1, error_line)
+    }
+
+    /// Compute what the standard file descriptors of a stage are connected
+    /// to, applying its redirections in order on top of `io`. Files are
+    /// opened here, so that an error is reported for the path that could not
+    /// be opened.
+    fn setup_io(
+        &mut self,
+        redirects: &[Redirect],
+        mut io: [Io; 3],
+    ) -> io::Result<Result<[Io; 3], String>> {
+        for redirect in redirects {
+            let mut options = OpenOptions::new();
+            let target = match &redirect.kind {
+                RedirectKind::Dup(fd) => {
+                    io[redirect.fd as usize] = io[*fd as usize].clone();
+                    continue;
+                }
+                RedirectKind::Input(target) => {
+                    options.read(true);
+                    target
+                }
+                RedirectKind::Output(target) => {
+                    options.write(true).create(true).truncate(true);
+                    target
+                }
+                RedirectKind::Append(target) => {
+                    options.append(true).create(true);
+                    target
+                }
+            };
+            let path = match expand_word(target, self)?.as_slice() {
+                [path] => path.clone(),
+                _ => return Ok(Err(format!("nrbt: ambiguous redirect: {}", target))),
+            };
+            let file = match options.open(self.scope.cwd.join(&path)) {
+                Ok(file) => file,
+                Err(error) => return Ok(Err(format!("nrbt: cannot open {}: {}", path, error))),
+            };
+            io[redirect.fd as usize] = Io::File(Rc::new(file), path);
+        }
+
+        Ok(Ok(io))
+    }
+
+    /// Run the stages of a pipeline, each one reading the stdout of the
+    /// previous one. The stderr of every stage and the stdout of the last one
+    /// are captured, and every stage is waited for. The pipeline status is the
+    /// one of the last stage or, with `pipefail`, the one of the last stage
+    /// that failed.
+    ///
+    /// Like in the shell, builtins and assignments only change the current
+    /// scope when the pipeline has a single stage. The stages run in their own
+    /// process group, so that they can be terminated together. Nothing is run
+    /// anymore once the run timed out or was interrupted by a signal.
+    fn run_pipeline(&mut self, pipeline: &[Stage]) -> Result<(), io::Error> {
+        if self.stopped() {
+            return Ok(());
+        }
+        let mut cmds: Vec<&Cmd> = Vec::new();
+        for stage in pipeline {
+            // The parser only lets groups appear alone in their pipeline.
+            match stage {
+                Stage::Cmd(cmd) => cmds.push(cmd),
+                Stage::Subshell(body, redirects) => {
+                    return self.run_group(stage, body, redirects, true);
+                }
+                Stage::Group(body, redirects) => {
+                    return self.run_group(stage, body, redirects, false);
+                }
+            }
+        }
+
+        let alone = cmds.len() == 1;
+        let mut stages: Vec<(Vec<String>, Instant, Result<Spawned, StepReturn>)> = Vec::new();
+        let mut stdin: Option<Stdio> = None;
+        // Command substitutions run their own pipelines while this one is
+        // being spawned.
+        let outer_group = pgroup::current_group();
+        let mut pgid = 0;
+        // Processes left behind and only reported may hold the captured
+        // output open long after the pipeline ended.
+        let stop_reading = match self.leftovers {
+            Leftovers::Report => Some(Arc::new(AtomicBool::new(false))),
+            _ => None,
+        };
+
+        for (i, cmd) in cmds.iter().enumerate() {
+            self.subst_status = None;
+            let argv = expand_words(&cmd.argv, self)?;
+            let mut env = Vec::new();
+            for (name, value) in &cmd.assignments {
+                env.push((name.clone(), expand_string(value, self)?));
+            }
+            let start = Instant::now();
+            let mut io = self.scope.io.clone();
+            if i > 0 {
+                io[0] = Io::PreviousStage;
+            }
+            if i < cmds.len() - 1 {
+                io[1] = Io::NextStage;
+            }
+            let io = match self.setup_io(&cmd.redirects, io)? {
+                Ok(io) => io,
+                Err(error_line) => {
+                    // A stage that could not be spawned leaves its reader with no input.
+                    stdin = None;
+                    let step = error_step(&argv, start, 1, error_line);
+                    stages.push((argv, start, Err(step)));
+                    continue;
+                }
+            };
+            if argv.is_empty() {
+                // Like in the shell, a command expanding to nothing only
+                // assigns its variables, and has the status of its last
+                // command substitution.
+                stdin = None;
+                let assignments: Vec<String> = env
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                let mut step = error_step(&assignments, start, 0, String::new());
+                if let Some((status, signal)) = self.subst_status {
+                    step.status = status;
+                    step.signal = signal;
+                }
+                if alone {
+                    self.scope.vars.extend(env);
+                }
+                stages.push((argv, start, Err(step)));
+                continue;
+            }
+            if alone && argv[0] == "cd" {
+                let step = self.change_dir(&argv, start);
+                stages.push((argv, start, Err(step)));
+                continue;
+            }
+            let reading = |stream| Reading {
+                stop: stop_reading.clone(),
+                ..self.reading(stream)
+            };
+            let spawned = spawn_stage(&argv, &env, &self.scope, &io, stdin.take(), pgid, &reading);
+            let stage = match spawned {
+                Ok((stage, next_stdin)) => {
+                    if pgid == 0 {
+                        pgid = stage.child.id() as i32;
+                        self.enter_group(pgid);
+                    }
+                    stdin = next_stdin;
+                    Ok(stage)
+                }
+                Err(error) => Err(failed_step(&argv, start, error)),
+            };
+            stages.push((argv, start, stage));
+        }
+        let mut last_status = (None, None);
+        let mut last_failure = None;
+        let mut exited = Vec::new();
+        for (argv, start, stage) in stages {
+            exited.push(match stage {
+                Ok(mut stage) => {
+                    let step = exited_step(&argv, start, stage.child.wait()?);
+                    (step, Some((start, stage)))
+                }
+                Err(step) => (step, None),
+            });
+        }
+
+        // Processes left behind may keep the captured output open, so they
+        // are looked for before reading it until the end: they are either
+        // killed, or the output is only read for what is already written.
+        if pgid != 0 && self.leftovers != Leftovers::Ignore {
+            let leftovers = pgroup::group_members(pgid);
+            if !leftovers.is_empty() && self.leftovers == Leftovers::Kill {
+                pgroup::terminate_group(pgid, self.kill_after);
+            }
+            if !leftovers.is_empty() {
+                if let Some(stop) = &stop_reading {
+                    stop.store(true, Ordering::SeqCst);
+                }
+            }
+            self.cmd_return.leftovers.extend(leftovers);
+        }
+
+        for (mut step, stage) in exited {
+            if let Some((start, stage)) = stage {
+                for (stream, reader) in stage.readers {
+                    let captured = join_output(reader)?;
+                    let matches = captured.matches.into_iter();
+                    self.cmd_return
+                        .matches
+                        .extend(matches.map(|regex| (stream, regex)));
+                    for (time, data) in captured.chunks {
+                        match stream {
+                            Stream::Stdout => step.stdout.extend_from_slice(&data),
+                            Stream::Stderr => step.stderr.extend_from_slice(&data),
+                        }
+                        step.chunks.push(Chunk {
+                            stream,
+                            offset: time - start,
+                            data,
+                        });
+                    }
+                }
+                step.chunks.sort_by_key(|chunk| chunk.offset);
+                step.stdout_redirect = stage.stdout_redirect;
+                step.stderr_redirect = stage.stderr_redirect;
+            } else {
+                self.match_error(&step);
+            }
+
+            last_status = (step.status, step.signal);
+            if !step.succeeded() {
+                last_failure = Some(last_status);
+            }
+            push_step(&mut self.cmd_return, step);
+        }
+
+        self.enter_group(outer_group);
+
+        let (status, signal) = match last_failure {
+            Some(failure) if self.pipefail => failure,
+            _ => last_status,
+        };
+        self.cmd_return.status = status;
+        self.cmd_return.signal = signal;
+
+        Ok(())
+    }
+}
+
+/// What to do with the processes a pipeline leaves running in its process
+/// group, like daemons it started.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Leftovers {
+    Ignore,
+    Report,
+    /// Report then terminate them.
+    Kill,
+}
+
+/// How to run a command line.
+pub struct RunOptions {
+    pub pipefail: bool,
+    pub leftovers: Leftovers,
+    pub timeout: Option<Duration>,
+    /// How long to wait after SIGTERM before sending SIGKILL, on timeout or
+    /// when terminating leftover processes.
+    pub kill_after: Duration,
+    /// Copy the captured output to nrbt's stdout and stderr as it arrives.
+    pub tee: bool,
+    /// How much output of each stream to keep.
+    pub max_output: Option<Limit>,
+    /// With `max_output`, keep the full streams in temporary files.
+    pub spill_output: bool,
+    /// The regexes to look for in the output.
+    pub patterns: Vec<(Stream, Regex)>,
+    /// Where to write the output as it arrives.
+    pub log: Option<Arc<LiveLog>>,
+}
+
+pub fn run_all_cmd(node: &Node, options: &RunOptions) -> Result<CmdReturn, io::Error> {
+    let mut executor = Executor {
+        cmd_return: CmdReturn::new(),
+        pipefail: options.pipefail,
+        leftovers: options.leftovers,
+        kill_after: options.kill_after,
+        scope: Scope::new(),
+        subst_status: None,
+        watchdog: options
+            .timeout
+            .map(|timeout| Watchdog::start(timeout, options.kill_after)),
+        tee: options.tee,
+        bounds: options.max_output.map(|limit| {
+            Arc::new(Bounds {
+                limit,
+                spill: options.spill_output,
+                patterns: options.patterns.clone(),
+            })
+        }),
+        log: options.log.clone(),
+        substituting: false,
+        terminal: pgroup::owns_terminal(),
+    };
+    let result = executor.run_node(node);
+    if let Some(watchdog) = executor.watchdog.take() {
+        executor.cmd_return.timed_out = watchdog.stop();
+    }
+    executor.cmd_return.interrupted = signals::received();
+    executor.cmd_return.bounded = options.max_output.is_some();
+    result?;
+    Ok(executor.cmd_return)
+}
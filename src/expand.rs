@@ -0,0 +1,179 @@
+use crate::glob::{glob, has_glob, PatternChar};
+use crate::parser::{Node, Word, WordPart};
+use std::ffi::{CStr, CString};
+use std::io;
+use std::path::Path;
+use std::process;
+
+/// What expanding words needs from the scope they are expanded in.
+pub trait Context {
+    /// The value of a variable, if set.
+    fn var(&self, name: &str) -> Option<String>;
+    /// The directory relative patterns are matched from.
+    fn cwd(&self) -> &Path;
+    /// Run the commands of a command substitution and return their output.
+    fn substitute(&mut self, node: &Node) -> io::Result<String>;
+}
+
+/// A field resulting from the expansion of a word. Characters coming from
+/// unquoted text may still act as pattern characters.
+type Field = Vec<PatternChar>;
+
+fn lookup(name: &str, ctx: &dyn Context) -> String {
+    if name == "$" {
+        return process::id().to_string();
+    }
+    ctx.var(name).unwrap_or_default()
+}
+
+/// The home directory of `user`, or of the current user when empty.
+fn home_dir(user: &str, ctx: &dyn Context) -> Option<String> {
+    if user.is_empty() {
+        if let Some(home) = ctx.var("HOME") {
+            return Some(home);
+        }
+    }
+
+    let passwd = unsafe {
+        if user.is_empty() {
+            libc::getpwuid(libc::getuid())
+        } else {
+            let user = CString::new(user).ok()?;
+            libc::getpwnam(user.as_ptr())
+        }
+    };
+    if passwd.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr((*passwd).pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+/// Split the tilde-prefix off the start of a word: a leading `~` followed by
+/// unquoted characters up to the first `/`. Returns the home directory it
+/// stands for and what remains of the first part of the word.
+fn expand_tilde<'a>(word: &'a Word, ctx: &dyn Context) -> Option<(String, &'a str)> {
+    let text = match word.parts.first() {
+        Some(WordPart::Literal(text)) if text.starts_with('~') => text,
+        _ => return None,
+    };
+    let end = match text.find('/') {
+        Some(end) => end,
+        // Quoted characters cannot be part of a user name.
+        None if word.parts.len() > 1 => return None,
+        None => text.len(),
+    };
+    Some((home_dir(&text[1..end], ctx)?, &text[end..]))
+}
+
+/// The value of an expansion, and whether it was quoted.
+fn expand_part(part: &WordPart, ctx: &mut dyn Context) -> io::Result<(String, bool)> {
+    Ok(match part {
+        WordPart::Literal(text) => (text.clone(), false),
+        WordPart::Quoted(text) => (text.clone(), true),
+        WordPart::Var { name, quoted } => (lookup(name, ctx), *quoted),
+        WordPart::CmdSubst { node, quoted } => {
+            let mut output = ctx.substitute(node)?;
+            let len = output.trim_end_matches('\n').len();
+            output.truncate(len);
+            (output, *quoted)
+        }
+    })
+}
+
+/// Expand a word into the fields it stands for. A leading `~` is replaced by
+/// a home directory, parameters and command substitutions by their value
+/// and, when unquoted, split on blanks like the shell does with the default
+/// `IFS`.
+fn expand_fields(word: &Word, ctx: &mut dyn Context) -> io::Result<Vec<Field>> {
+    let mut fields = Vec::new();
+    let mut field: Option<Field> = None;
+    let mut parts = word.parts.iter();
+
+    if let Some((home, rest)) = expand_tilde(word, ctx) {
+        let field = field.get_or_insert_with(Vec::new);
+        field.extend(home.chars().map(|c| (c, false)));
+        field.extend(rest.chars().map(|c| (c, true)));
+        parts.next();
+    }
+
+    for part in parts {
+        let (value, quoted) = expand_part(part, ctx)?;
+        if quoted || matches!(part, WordPart::Literal(_)) {
+            let field = field.get_or_insert_with(Vec::new);
+            field.extend(value.chars().map(|c| (c, !quoted)));
+            continue;
+        }
+
+        if value.starts_with(is_blank) {
+            fields.extend(field.take());
+        }
+        let mut words = value.split(is_blank).filter(|word| !word.is_empty());
+        if let Some(first) = words.next() {
+            let field = field.get_or_insert_with(Vec::new);
+            field.extend(first.chars().map(|c| (c, true)));
+        }
+        for word in words {
+            fields.extend(field.replace(word.chars().map(|c| (c, true)).collect()));
+        }
+        if value.ends_with(is_blank) {
+            fields.extend(field.take());
+        }
+    }
+
+    fields.extend(field);
+    Ok(fields)
+}
+
+fn to_string(field: &[PatternChar]) -> String {
+    field.iter().map(|&(c, _)| c).collect()
+}
+
+/// Expand a word into fields, without pathname expansion.
+pub fn expand_word(word: &Word, ctx: &mut dyn Context) -> io::Result<Vec<String>> {
+    Ok(expand_fields(word, ctx)?
+        .iter()
+        .map(|field| to_string(field))
+        .collect())
+}
+
+/// Expand words into fields, replacing the fields containing unquoted
+/// pattern characters by the paths they match. As in POSIX shells, a pattern
+/// matching nothing is kept as is.
+pub fn expand_words(words: &[Word], ctx: &mut dyn Context) -> io::Result<Vec<String>> {
+    let mut fields = Vec::new();
+    for word in words {
+        for field in expand_fields(word, ctx)? {
+            let paths = if has_glob(&field) {
+                glob(&field, ctx.cwd())
+            } else {
+                Vec::new()
+            };
+            if paths.is_empty() {
+                fields.push(to_string(&field));
+            } else {
+                fields.extend(paths);
+            }
+        }
+    }
+    Ok(fields)
+}
+
+/// Expand a word without splitting it, as done for assignment values.
+pub fn expand_string(word: &Word, ctx: &mut dyn Context) -> io::Result<String> {
+    let mut value = String::new();
+    let mut parts = word.parts.iter();
+    if let Some((home, rest)) = expand_tilde(word, ctx) {
+        value.push_str(&home);
+        value.push_str(rest);
+        parts.next();
+    }
+    for part in parts {
+        value.push_str(&expand_part(part, ctx)?.0);
+    }
+    Ok(value)
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n'
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::path::Path;
+
+/// A pattern character, along with whether it may act as a special pattern
+/// character (it does not when it was quoted).
+pub type PatternChar = (char, bool);
+
+pub fn has_glob(pattern: &[PatternChar]) -> bool {
+    pattern
+        .iter()
+        .any(|&(c, active)| active && (c == '*' || c == '?' || c == '['))
+}
+
+/// Match `name` against a pattern made of `*`, `?` and `[...]` bracket
+/// expressions.
+fn matches(pattern: &[PatternChar], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to resume when the last `*` has to match one more character.
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some(('*', true)) => {
+                star = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some(('?', true)) => Some(1),
+            Some(('[', true)) => match match_bracket(&pattern[p..], name[n]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                None if name[n] == '[' => Some(1),
+                None => None,
+            },
+            Some(&(c, _)) if c == name[n] => Some(1),
+            _ => None,
+        };
+
+        match (step, star) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            (None, Some((star_p, star_n))) => {
+                p = star_p;
+                n = star_n + 1;
+                star = Some((star_p, star_n + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&(c, active)| active && c == '*')
+}
+
+/// Match `c` against the bracket expression starting `pattern`. Returns
+/// whether it matched and the length of the expression, or `None` if the
+/// bracket is not closed and must be taken literally.
+fn match_bracket(pattern: &[PatternChar], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some(('!', true)) | Some(('^', true)));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let (start, active) = *pattern.get(i)?;
+        if start == ']' && active && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+
+        if start == '[' && active && pattern.get(i + 1) == Some(&(':', true)) {
+            let class: String = pattern[i + 2..]
+                .iter()
+                .take_while(|&&(c, _)| c != ':')
+                .map(|&(c, _)| c)
+                .collect();
+            let end = i + 2 + class.len();
+            if pattern.get(end) == Some(&(':', true)) && pattern.get(end + 1) == Some(&(']', true))
+            {
+                matched |= match_class(&class, c);
+                i = end + 2;
+                continue;
+            }
+        }
+
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some(('-', true)), Some(&(end, _))) if end != ']' => {
+                matched |= start <= c && c <= end;
+                i += 3;
+            }
+            _ => {
+                matched |= start == c;
+                i += 1;
+            }
+        }
+    }
+}
+
+fn match_class(class: &str, c: char) -> bool {
+    match class {
+        "alnum" => c.is_alphanumeric(),
+        "alpha" => c.is_alphabetic(),
+        "blank" => c == ' ' || c == '\t',
+        "digit" => c.is_ascii_digit(),
+        "lower" => c.is_lowercase(),
+        "punct" => c.is_ascii_punctuation(),
+        "space" => c.is_whitespace(),
+        "upper" => c.is_uppercase(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+/// Expand a pattern into the sorted list of existing paths it matches.
+/// Following POSIX, a leading `.` in a file name has to be matched
+/// explicitly, and `/` is never matched by a special character. Relative
+/// patterns are matched from `cwd`, and expand to relative paths.
+pub fn glob(pattern: &[PatternChar], cwd: &Path) -> Vec<String> {
+    let absolute = pattern.first().map(|&(c, _)| c) == Some('/');
+    let dirs_only = pattern.last().map(|&(c, _)| c) == Some('/');
+    let components: Vec<&[PatternChar]> = pattern
+        .split(|&(c, _)| c == '/')
+        .filter(|component| !component.is_empty())
+        .collect();
+    let mut paths = vec![if absolute {
+        "/".to_string()
+    } else {
+        String::new()
+    }];
+
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let mut next = Vec::new();
+
+        for path in &paths {
+            if !has_glob(component) {
+                let name: String = component.iter().map(|&(c, _)| c).collect();
+                next.push(join(path, &name));
+                continue;
+            }
+
+            let entries = match fs::read_dir(cwd.join(path)) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') && component[0].0 != '.' {
+                    continue;
+                }
+                let chars: Vec<char> = name.chars().collect();
+                if matches(component, &chars) {
+                    let path = join(path, &name);
+                    if (last && !dirs_only) || cwd.join(&path).is_dir() {
+                        next.push(path);
+                    }
+                }
+            }
+        }
+
+        paths = next;
+    }
+
+    let mut paths: Vec<String> = paths
+        .into_iter()
+        .filter(|path| cwd.join(path).symlink_metadata().is_ok())
+        .map(|path| if dirs_only { path + "/" } else { path })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
@@ -0,0 +1,40 @@
+/// Lines longer than this are split in pieces.
+pub const MAX_LINE: usize = 64 * 1024;
+
+/// Gathers output read in chunks into lines, along with when the first byte
+/// of each line was read.
+pub struct LineSplitter<T> {
+    line: Vec<u8>,
+    start: Option<T>,
+}
+
+impl<T: Copy> LineSplitter<T> {
+    pub fn new() -> LineSplitter<T> {
+        LineSplitter {
+            line: Vec::new(),
+            start: None,
+        }
+    }
+
+    /// Add a chunk of output read at `time`, calling `emit` with each line
+    /// it completes, without its newline.
+    pub fn push(&mut self, time: T, data: &[u8], mut emit: impl FnMut(T, &[u8])) {
+        for piece in data.split_inclusive(|&byte| byte == b'\n') {
+            let start = *self.start.get_or_insert(time);
+            self.line.extend_from_slice(piece);
+            if self.line.ends_with(b"\n") || self.line.len() >= MAX_LINE {
+                emit(start, self.line.strip_suffix(b"\n").unwrap_or(&self.line));
+                self.line.clear();
+                self.start = None;
+            }
+        }
+    }
+
+    /// Call `emit` with the last line, when the output does not end with a
+    /// newline.
+    pub fn finish(self, mut emit: impl FnMut(T, &[u8])) {
+        if let Some(start) = self.start {
+            emit(start, &self.line);
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use crate::exec::Stream;
+use crate::lines::LineSplitter;
+use crate::util::hostname;
+use chrono::format::{Item, StrftimeItems};
+use chrono::prelude::*;
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::fs::{FileExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+const RUNNING: &str = "running, this log is incomplete";
+const COMPLETE: &str = "complete";
+
+/// Expand the placeholders of an output file path: strftime-style ones like
+/// `%Y%m%d`, with `time`, then `{hostname}` and `{pid}`.
+pub fn expand_path(template: &str, time: DateTime<Local>) -> Result<PathBuf, String> {
+    let items: Vec<Item> = StrftimeItems::new(template).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return Err(format!("invalid placeholder in `{}`", template));
+    }
+    let path = time.format_with_items(items.into_iter()).to_string();
+    let path = path
+        .replace("{hostname}", &hostname())
+        .replace("{pid}", &process::id().to_string());
+    Ok(PathBuf::from(path))
+}
+
+/// How the output file is written.
+pub struct OutputOptions {
+    /// Add the log after the content of the file instead of replacing it.
+    pub append: bool,
+    /// Write the log to a temporary file next to the output file, renamed
+    /// over it once complete.
+    pub atomic: bool,
+    /// The permissions of the file, when not the default ones.
+    pub mode: Option<u32>,
+}
+
+/// The output file, written while the command runs so that something is on
+/// disk even if nrbt or the machine dies. It starts with a header whose
+/// status tells whether the log is complete, followed by the output as it
+/// arrives, replaced by the report once the run ended.
+pub struct LiveLog {
+    file: Mutex<File>,
+    /// A handle on the file to mark the log complete at `status_offset`,
+    /// which an appended file does not allow.
+    status_file: File,
+    append: bool,
+    start: Instant,
+    status_offset: u64,
+    output_offset: u64,
+    /// The temporary file being written and the output file it replaces,
+    /// when written atomically.
+    rename: Option<(PathBuf, PathBuf)>,
+}
+
+/// Create the temporary file an atomic log is written to, next to `path`.
+/// A file already there, possibly planted by another user, is never opened.
+/// It gets the permissions of the file it replaces, unless `mode` is given.
+fn create_temp(path: &Path, mode: Option<u32>) -> io::Result<(File, PathBuf)> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mode = match (mode, fs::metadata(path)) {
+        (Some(mode), _) => Some(mode),
+        (None, Ok(metadata)) => Some(metadata.permissions().mode() & 0o7777),
+        (None, Err(_)) => None,
+    };
+    let mut n = 0;
+    loop {
+        let temp = path.with_file_name(format!(".{}.nrbt-{}-{}", name, process::id(), n));
+        let mut open = OpenOptions::new();
+        open.write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW);
+        if let Some(mode) = mode {
+            open.mode(mode);
+        }
+        match open.open(&temp) {
+            Ok(file) => {
+                if let Some(mode) = mode {
+                    // Not restricted by the umask.
+                    file.set_permissions(Permissions::from_mode(mode))?;
+                }
+                return Ok((file, temp));
+            }
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+impl LiveLog {
+    pub fn create(
+        path: &Path,
+        options: &OutputOptions,
+        cmd_line: &str,
+        start_time: DateTime<Local>,
+    ) -> io::Result<LiveLog> {
+        let (mut file, rename) = if options.atomic {
+            let (file, temp) = create_temp(path, options.mode)?;
+            (file, Some((temp, path.to_path_buf())))
+        } else {
+            let mut open = OpenOptions::new();
+            if options.append {
+                // Runs appending to the same file never overwrite each other.
+                open.append(true).create(true);
+            } else {
+                open.write(true).create(true).truncate(true);
+            }
+            if let Some(mode) = options.mode {
+                open.mode(mode);
+            }
+            let file = open.open(path)?;
+            if let Some(mode) = options.mode {
+                // Not restricted by the umask.
+                file.set_permissions(Permissions::from_mode(mode))?;
+            }
+            (file, None)
+        };
+        let status_file = if options.append {
+            let status_file = OpenOptions::new().write(true).open(path)?;
+            let (metadata, status_metadata) = (file.metadata()?, status_file.metadata()?);
+            if (metadata.dev(), metadata.ino()) != (status_metadata.dev(), status_metadata.ino()) {
+                return Err(io::Error::other(format!("{} was replaced", path.display())));
+            }
+            status_file
+        } else {
+            file.try_clone()?
+        };
+
+        let header = format!(
+            "Log of command: \"{}\"\nPID: {}\nStarted at: {}\nStatus: ",
+            cmd_line,
+            process::id(),
+            start_time.to_rfc2822()
+        );
+        // Written at once, so that it ends up in one piece when appending.
+        file.write_all(format!("{}{}\n\n", header, RUNNING).as_bytes())?;
+        file.sync_data()?;
+        let output_offset = file.stream_position()?;
+        Ok(LiveLog {
+            file: Mutex::new(file),
+            status_file,
+            append: options.append,
+            start: Instant::now(),
+            status_offset: output_offset - (RUNNING.len() + 2) as u64,
+            output_offset,
+            rename,
+        })
+    }
+
+    fn write(&self, text: &[u8]) {
+        if let Ok(mut file) = self.file.lock() {
+            // Errors must not stop the run, the report tells how it went.
+            let _ = file.write_all(text);
+        }
+    }
+
+    /// Write a line of output of `stream`, whose start was read at `time`.
+    fn write_line(&self, time: Instant, stream: Stream, line: &[u8]) {
+        let offset = time.saturating_duration_since(self.start);
+        let mut text = format!("[+{:.3}s {}] ", offset.as_secs_f64(), stream.name()).into_bytes();
+        text.extend_from_slice(line);
+        text.push(b'\n');
+        self.write(&text);
+    }
+
+    /// Write a line from nrbt among the output.
+    pub fn note(&self, note: &str) {
+        self.write(format!("[nrbt: {}]\n", note).as_bytes());
+    }
+
+    /// Replace the output by the report, then mark the log complete, and
+    /// move it in place when written atomically. An appended file is never
+    /// truncated, as other runs may have appended to it meanwhile: the
+    /// report follows the output there.
+    pub fn finish(&self, report: &[u8]) -> io::Result<()> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("output file poisoned"))?;
+        if !self.append {
+            file.set_len(self.output_offset)?;
+            file.seek(SeekFrom::Start(self.output_offset))?;
+        }
+        file.write_all(report)?;
+        file.sync_data()?;
+        let status = format!("{:width$}", COMPLETE, width = RUNNING.len());
+        self.status_file
+            .write_all_at(status.as_bytes(), self.status_offset)?;
+        self.status_file.sync_data()?;
+        if let Some((temp, path)) = &self.rename {
+            fs::rename(temp, path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the output of a stream to the log line by line, as it is read.
+pub struct LineWriter {
+    log: Arc<LiveLog>,
+    stream: Stream,
+    lines: LineSplitter<Instant>,
+}
+
+impl LineWriter {
+    pub fn new(log: Arc<LiveLog>, stream: Stream) -> LineWriter {
+        LineWriter {
+            log,
+            stream,
+            lines: LineSplitter::new(),
+        }
+    }
+
+    pub fn push(&mut self, time: Instant, data: &[u8]) {
+        let (log, stream) = (&self.log, self.stream);
+        self.lines.push(time, data, |start, line| {
+            log.write_line(start, stream, line)
+        });
+    }
+
+    pub fn finish(self) {
+        let (log, stream) = (&self.log, self.stream);
+        self.lines
+            .finish(|start, line| log.write_line(start, stream, line));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::duration::parse_duration;
+use crate::util::stable_hash;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What to do when another run holds the lock.
+#[derive(Clone, Copy, PartialEq)]
+pub enum LockPolicy {
+    /// Do not run, silently.
+    Skip,
+    /// Do not run, and print a report saying so.
+    Report,
+    /// Wait for the lock, forever or up to a duration, then report.
+    Wait(Option<Duration>),
+    /// Terminate the run holding the lock, then take it.
+    Kill,
+}
+
+impl LockPolicy {
+    /// Parse a policy among `skip`, `report`, `wait`, `wait:DURATION` and
+    /// `kill`.
+    pub fn parse(text: &str) -> Option<LockPolicy> {
+        match text {
+            "skip" => Some(LockPolicy::Skip),
+            "report" => Some(LockPolicy::Report),
+            "wait" => Some(LockPolicy::Wait(None)),
+            "kill" => Some(LockPolicy::Kill),
+            _ if text.starts_with("wait:") => {
+                parse_duration(&text["wait:".len()..]).map(|max| LockPolicy::Wait(Some(max)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An exclusive lock on a file, held until it is dropped. The file contains
+/// the PID of its holder, and is emptied on release.
+pub struct Lock {
+    file: File,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// Why the command was not run.
+pub struct Busy {
+    pub path: PathBuf,
+    /// The PID recorded by the run holding the lock.
+    pub pid: Option<i32>,
+    /// How long nrbt waited for the lock.
+    pub waited: Option<Duration>,
+}
+
+/// The lock used for `cmd_line` when no path is given, in the private runtime
+/// directory of the user when there is one, else in the temporary directory.
+/// The temporary directory is shared, so the name there holds the user ID:
+/// runs of the same command by other users neither block nor kill each
+/// other.
+pub fn default_lock_path(cmd_line: &str) -> PathBuf {
+    let hash = stable_hash(cmd_line.as_bytes());
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join(format!("nrbt-{:016x}.lock", hash)),
+        _ => env::temp_dir().join(format!(
+            "nrbt-{}-{:016x}.lock",
+            unsafe { libc::geteuid() },
+            hash
+        )),
+    }
+}
+
+fn try_lock(file: &File) -> io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(error),
+    }
+}
+
+/// Open the lock file, refusing symbolic and hard links: the file gets
+/// truncated, and a link planted in a shared directory would have it
+/// truncate another file.
+fn open(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.nlink() != 1 {
+        return Err(io::Error::other(format!(
+            "{} is not a plain lock file",
+            path.display()
+        )));
+    }
+    Ok(file)
+}
+
+fn recorded_pid(file: &mut File) -> Option<i32> {
+    let mut text = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut text).ok()?;
+    text.trim().parse().ok()
+}
+
+fn is_alive(pid: i32) -> bool {
+    let alive = unsafe { libc::kill(pid, 0) == 0 };
+    alive || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Poll the lock until it is taken or `max` elapsed.
+fn wait_lock(file: &File, max: Option<Duration>) -> io::Result<bool> {
+    let start = Instant::now();
+    while !try_lock(file)? {
+        if max.is_some_and(|max| start.elapsed() >= max) {
+            return Ok(false);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Ok(true)
+}
+
+/// Take the lock at `path`, following `policy` when another run holds it.
+/// The PID recorded in the file is only used to report and kill the holder:
+/// a held lock is never taken over, whatever its PID.
+pub fn acquire(
+    path: &Path,
+    policy: LockPolicy,
+    kill_after: Duration,
+) -> io::Result<Result<Lock, Busy>> {
+    let mut file = open(path)?;
+    if !try_lock(&file)? {
+        let pid = recorded_pid(&mut file);
+        let busy = |waited| Busy {
+            path: path.to_path_buf(),
+            pid,
+            waited,
+        };
+        let locked = match (pid, policy) {
+            (_, LockPolicy::Skip) | (_, LockPolicy::Report) => false,
+            (_, LockPolicy::Wait(max)) => {
+                let start = Instant::now();
+                if !wait_lock(&file, max)? {
+                    return Ok(Err(busy(Some(start.elapsed()))));
+                }
+                true
+            }
+            (Some(pid), LockPolicy::Kill) if is_alive(pid) => {
+                unsafe {
+                    libc::kill(pid, libc::SIGTERM);
+                }
+                if !wait_lock(&file, Some(kill_after))? {
+                    unsafe {
+                        libc::kill(pid, libc::SIGKILL);
+                    }
+                    wait_lock(&file, None)?;
+                }
+                true
+            }
+            // Without a live PID there is nothing to kill: the holder is
+            // about to record it, or is about to release the lock.
+            (_, LockPolicy::Kill) => wait_lock(&file, Some(kill_after))?,
+        };
+        if !locked {
+            return Ok(Err(busy(None)));
+        }
+    }
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    writeln!(file, "{}", process::id())?;
+    Ok(Ok(Lock { file }))
+}
@@ -1,41 +1,43 @@
+mod capture;
+mod duration;
+mod exec;
+mod expand;
+mod glob;
+mod lines;
+mod live_log;
+mod lock;
+mod parser;
+mod pgroup;
+mod plan;
+mod retry;
+mod signals;
+mod splay;
+mod util;
+mod watchdog;
+
+use capture::Limit;
 use chrono::prelude::*;
+use duration::{format_duration, parse_duration};
+use exec::{run_all_cmd, Chunk, CmdReturn, Leftovers, RunOptions, Stream};
 use getopts::Options;
-use regex::{Regex, RegexSet};
+use lines::LineSplitter;
+use live_log::{expand_path, LiveLog, OutputOptions};
+use lock::{default_lock_path, Busy, LockPolicy};
+use parser::{argv_cmd, parse_cmd_line, quote_argv};
+use plan::{format_json, format_text, plan};
+use regex::Regex;
+use retry::{Attempt, RetryOn, RetryPolicy};
+use signals::{forward_signals, signal_name};
+use splay::splay_delay;
 use std::env;
-use std::fs::File;
-use std::io::{self, ErrorKind, Write};
-use std::os::unix::process::ExitStatusExt;
-use std::process::Command;
-use std::process::{self, Child, Output, Stdio};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
 use std::str;
+use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, Instant};
 
-#[derive(PartialEq)]
-enum CmdKind {
-    Single,
-    Pipe,
-    And,
-    SemiCol,
-}
-
-struct Cmd<'a> {
-    kind: CmdKind,
-    cmd_line: &'a str,
-}
-
-struct CmdReturn {
-    status: Option<i32>,
-    signal: Option<i32>,
-    stderr: Vec<u8>,
-    stdout: Vec<u8>,
-}
-
-#[derive(PartialEq)]
-enum Run {
-    Continue,
-    Abort,
-}
-
 fn main() -> Result<(), io::Error> {
     let args: Vec<_> = env::args().collect();
     let program_name = args[0].clone();
@@ -43,9 +45,27 @@ fn main() -> Result<(), io::Error> {
     opts.optopt(
         "o",
         "output-file",
-        "Write stdout and stderr in a file.",
+        "Write stdout and stderr in a file. PATH can contain strftime-style \
+         placeholders like %Y%m%d, {hostname} and {pid}.",
         "PATH",
     );
+    opts.optflag(
+        "",
+        "append",
+        "Add to the output file instead of replacing it.",
+    );
+    opts.optflag(
+        "",
+        "atomic",
+        "Write the output file under a temporary name, renamed over it once \
+         the run ended.",
+    );
+    opts.optopt(
+        "",
+        "output-mode",
+        "Create the output file with these permissions, e.g. 0640.",
+        "MODE",
+    );
     opts.optmulti(
         "r",
         "stderr-match",
@@ -67,55 +87,452 @@ fn main() -> Result<(), io::Error> {
          Can be specified multiple times.",
         "CODE",
     );
+    opts.optflag(
+        "",
+        "pipefail",
+        "Make a pipeline fail when any of its commands fails, not only the \
+         last one.",
+    );
+    opts.optflag(
+        "",
+        "tee",
+        "Copy the output of the command to stdout and stderr as it is \
+         written, while still capturing it for the report.",
+    );
+    opts.optopt(
+        "",
+        "max-output",
+        "Only keep the first and the last SIZE of each captured stream, SIZE \
+         being a number of bytes with an optional k, M or G suffix, or of \
+         lines with an l suffix, e.g. 64k or 200l. Regexes are then matched \
+         line by line.",
+        "SIZE",
+    );
+    opts.optflag(
+        "",
+        "spill-output",
+        "With --max-output, keep the full streams whose output was dropped in \
+         temporary files, named in the report.",
+    );
+    opts.optflag(
+        "",
+        "combined-output",
+        "Add to the report the stdout and stderr of each step merged in the \
+         order they were written.",
+    );
+    opts.optflag(
+        "",
+        "timestamps",
+        "Same as --combined-output, prefixing each line with when it was \
+         written and its stream.",
+    );
+    opts.optopt(
+        "",
+        "timeout",
+        "Terminate the command when it runs for longer than DURATION, e.g. \
+         30s, 5m or 2h. A timeout is always reported.",
+        "DURATION",
+    );
+    opts.optopt(
+        "",
+        "kill-after",
+        "On timeout, send SIGKILL when the command is still running DURATION \
+         after SIGTERM. Defaults to 10s.",
+        "DURATION",
+    );
+    opts.optopt(
+        "",
+        "leftovers",
+        "Look for processes left running by the command in its process group, \
+         and list them in the report. With `kill`, also terminate them.",
+        "report|kill",
+    );
+    opts.optopt(
+        "",
+        "retries",
+        "Run the command again up to N times when it fails, the final run \
+         deciding whether to report.",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "retry-delay",
+        "Wait DURATION before the first retry. Defaults to 5s.",
+        "DURATION",
+    );
+    opts.optopt(
+        "",
+        "retry-backoff",
+        "Multiply the retry delay by FACTOR after each retry. Defaults to 2.",
+        "FACTOR",
+    );
+    opts.optopt(
+        "",
+        "retry-max-delay",
+        "Never wait longer than DURATION between retries. Defaults to 1h.",
+        "DURATION",
+    );
+    opts.optmulti(
+        "",
+        "retry-on",
+        "Only retry a failed run meeting one of these conditions: exit:CODES, \
+         timeout, stdout:REGEX or stderr:REGEX. Can be specified multiple \
+         times.",
+        "CONDITION",
+    );
+    opts.optopt(
+        "",
+        "splay",
+        "Wait for a random duration up to MAX before running the command, so \
+         that hosts running it at the same time do not all start together.",
+        "MAX",
+    );
+    opts.optflag(
+        "",
+        "splay-stable",
+        "Make the --splay delay depend only on the host name and the command \
+         line, so that each host keeps the same slot.",
+    );
+    opts.optflagopt(
+        "",
+        "lock",
+        "Do not run the command while another run holds the lock at PATH, \
+         which defaults to a file named after the command line in \
+         $XDG_RUNTIME_DIR, or in the temporary directory when it is not set.",
+        "PATH",
+    );
+    opts.optopt(
+        "",
+        "lock-policy",
+        "What to do when the lock is held: skip silently (the default), \
+         report the skip, wait for the lock, up to DURATION when given, or \
+         kill the run holding it.",
+        "skip|report|wait[:DURATION]|kill",
+    );
+    opts.optflagopt(
+        "",
+        "shell",
+        "Run the command line with `PATH -c` instead of parsing it, PATH \
+         defaulting to /bin/sh.",
+        "PATH",
+    );
+    opts.optflag(
+        "",
+        "check",
+        "Only check the syntax of the command line, without running it.",
+    );
+    opts.optflagopt(
+        "",
+        "dry-run",
+        "Print what would be run, as text or json, without running it.",
+        "FORMAT",
+    );
     opts.optflag("h", "help", "Print this help menu.");
-    let matches = match opts.parse(&args[1..]) {
+    // Everything after `--` is the argv of the command to run as is.
+    let (args, exec_argv) = match args.iter().position(|arg| arg == "--") {
+        Some(i) => (&args[1..i], Some(&args[i + 1..])),
+        None => (&args[1..], None),
+    };
+    let matches = match opts.parse(args) {
         Ok(m) => m,
         Err(f) => panic!("{}", f.to_string()),
     };
 
-    let output_file = matches.opt_str("o");
+    let output_file =
+        matches
+            .opt_str("o")
+            .map(|template| match expand_path(&template, Local::now()) {
+                Ok(path) => path,
+                Err(error) => {
+                    eprintln!("nrbt: -o: {}", error);
+                    process::exit(2);
+                }
+            });
+    let output_options = OutputOptions {
+        append: matches.opt_present("append"),
+        atomic: matches.opt_present("atomic"),
+        mode: matches
+            .opt_str("output-mode")
+            .map(|mode| match u32::from_str_radix(&mode, 8) {
+                Ok(mode) if mode <= 0o7777 => mode,
+                _ => {
+                    eprintln!("nrbt: invalid mode `{}` for --output-mode", mode);
+                    process::exit(2);
+                }
+            }),
+    };
+    if output_options.append && output_options.atomic {
+        eprintln!("nrbt: --append and --atomic cannot be combined");
+        process::exit(2);
+    }
     let error_codes = matches.opt_strs("e");
-    let stderr_match_exprs = matches.opt_strs("r");
-    let stdout_match_exprs = matches.opt_strs("u");
+    let stderr_regexes = regexes_opt(&matches, "r");
+    let stdout_regexes = regexes_opt(&matches, "u");
+    let max_output = matches
+        .opt_str("max-output")
+        .map(|size| match Limit::parse(&size) {
+            Some(limit) => limit,
+            None => {
+                eprintln!("nrbt: invalid size `{}` for --max-output", size);
+                process::exit(2);
+            }
+        });
+    let pipefail = matches.opt_present("pipefail");
+    let combined_output = if matches.opt_present("timestamps") {
+        CombinedOutput::Timestamped
+    } else if matches.opt_present("combined-output") {
+        CombinedOutput::Plain
+    } else {
+        CombinedOutput::Off
+    };
+    let timeout = duration_opt(&matches, "timeout");
+    let kill_after = duration_opt(&matches, "kill-after").unwrap_or(Duration::from_secs(10));
+    let leftovers = match matches.opt_str("leftovers").as_deref() {
+        None => Leftovers::Ignore,
+        Some("report") => Leftovers::Report,
+        Some("kill") => Leftovers::Kill,
+        Some(mode) => {
+            eprintln!("nrbt: unknown leftovers mode `{}`", mode);
+            process::exit(2);
+        }
+    };
+    let retries = match matches.opt_str("retries").map(|n| n.parse()) {
+        None => 0,
+        Some(Ok(retries)) => retries,
+        Some(Err(_)) => {
+            eprintln!("nrbt: invalid number of retries");
+            process::exit(2);
+        }
+    };
+    let retry_backoff = match matches.opt_str("retry-backoff").map(|f| f.parse::<f64>()) {
+        None => 2.0,
+        Some(Ok(backoff)) if backoff >= 1.0 && backoff.is_finite() => backoff,
+        Some(_) => {
+            eprintln!("nrbt: the retry backoff must be a finite number no less than 1");
+            process::exit(2);
+        }
+    };
+    let mut retry_conditions = Vec::new();
+    for condition in matches.opt_strs("retry-on") {
+        match RetryOn::parse(&condition) {
+            Ok(condition) => retry_conditions.push(condition),
+            Err(error) => {
+                eprintln!("nrbt: --retry-on: {}", error);
+                process::exit(2);
+            }
+        }
+    }
+    let retry_policy = RetryPolicy {
+        retries,
+        delay: duration_opt(&matches, "retry-delay").unwrap_or(Duration::from_secs(5)),
+        backoff: retry_backoff,
+        max_delay: duration_opt(&matches, "retry-max-delay").unwrap_or(Duration::from_secs(3600)),
+        conditions: retry_conditions,
+    };
+    let lock_policy = match matches.opt_str("lock-policy") {
+        None => LockPolicy::Skip,
+        Some(policy) => match LockPolicy::parse(&policy) {
+            Some(policy) => policy,
+            None => {
+                eprintln!("nrbt: unknown lock policy `{}`", policy);
+                process::exit(2);
+            }
+        },
+    };
     if matches.opt_present("h") {
         print_usage(&program_name, &opts);
         process::exit(0);
     }
 
-    let cmd_line = if !matches.free.is_empty() {
-        matches.free[0].clone()
+    let cmd_line = match (exec_argv, matches.free.as_slice()) {
+        (Some(_), [arg, ..]) | (None, [_, arg, ..]) => {
+            eprintln!("nrbt: unexpected argument `{}`", arg);
+            process::exit(2);
+        }
+        (Some([]), []) | (None, []) => {
+            print_usage(&program_name, &opts);
+            process::exit(0);
+        }
+        (Some(_), []) if matches.opt_present("shell") => {
+            eprintln!("nrbt: --shell takes a command line, not an argv after `--`");
+            process::exit(2);
+        }
+        (Some(argv), []) => quote_argv(argv),
+        (None, [cmd_line]) => cmd_line.clone(),
+    };
+
+    let node = if let Some(argv) = exec_argv {
+        argv_cmd(argv)
+    } else if matches.opt_present("shell") {
+        let shell = matches
+            .opt_str("shell")
+            .unwrap_or_else(|| "/bin/sh".to_string());
+        argv_cmd(&[shell, "-c".to_string(), cmd_line.clone()])
     } else {
-        print_usage(&program_name, &opts);
+        match parse_cmd_line(&cmd_line) {
+            Ok(node) => node,
+            Err(error) => {
+                eprintln!("nrbt: {}", error.diagnostic(&cmd_line));
+                process::exit(2);
+            }
+        }
+    };
+    if matches.opt_present("check") {
+        process::exit(0);
+    }
+    if matches.opt_present("dry-run") {
+        let plan = plan(&node)?;
+        match matches.opt_str("dry-run").as_deref() {
+            None | Some("text") => {
+                println!("Plan of command: \"{}\"\n", cmd_line);
+                print!("{}", format_text(&plan));
+            }
+            Some("json") => println!("{}", format_json(&plan)),
+            Some(format) => {
+                eprintln!("nrbt: unknown dry-run format `{}`", format);
+                process::exit(2);
+            }
+        }
         process::exit(0);
+    }
+
+    // Not counted in the duration of the run.
+    let splay = duration_opt(&matches, "splay")
+        .map(|max| splay_delay(max, matches.opt_present("splay-stable"), &cmd_line));
+    if let Some(splay) = splay {
+        thread::sleep(splay);
+    }
+
+    let lock_path = if matches.opt_present("lock") {
+        let path = matches.opt_str("lock");
+        Some(path.map_or_else(|| default_lock_path(&cmd_line), PathBuf::from))
+    } else {
+        None
+    };
+    // Held until nrbt exits.
+    let _lock = match lock_path {
+        Some(path) => match lock::acquire(&path, lock_policy, kill_after)? {
+            Ok(lock) => Some(lock),
+            Err(busy) => {
+                if lock_policy != LockPolicy::Skip {
+                    let report = make_busy_report(&cmd_line, &busy)?;
+                    if let Some(path) = &output_file {
+                        LiveLog::create(path, &output_options, &cmd_line, Local::now())?
+                            .finish(&report)?;
+                    }
+                    println!("{}", String::from_utf8_lossy(&report));
+                }
+                process::exit(0);
+            }
+        },
+        None => None,
     };
 
     let start = Instant::now();
     let start_time = Local::now();
-    let run = run_all_cmd(parse_cmd_line(&cmd_line))?;
-    let end_time = Local::now();
-    let duration = start.elapsed();
-    let report = make_report(cmd_line, &run, &duration, start_time, end_time)?;
-    let mut stderr_matches_regex = false;
-    let mut stdout_matches_regex = false;
-
-    if !stderr_match_exprs.is_empty() {
-        let stderr_match_re_set = RegexSet::new(&stderr_match_exprs).unwrap();
-        stderr_matches_regex = stderr_match_re_set.is_match(str::from_utf8(&run.stderr).unwrap());
-    }
-
-    if !stdout_match_exprs.is_empty() {
-        let stdout_match_re_set = RegexSet::new(&stdout_match_exprs).unwrap();
-        stdout_matches_regex = stdout_match_re_set.is_match(str::from_utf8(&run.stdout).unwrap());
-    }
+    // Written as the command runs, to be on disk even if nrbt dies.
+    let log = match &output_file {
+        Some(path) => Some(Arc::new(LiveLog::create(
+            path,
+            &output_options,
+            &cmd_line,
+            start_time,
+        )?)),
+        None => None,
+    };
+    // Looked for as the output is read, in case it is bounded.
+    let mut patterns: Vec<(Stream, Regex)> = Vec::new();
+    patterns.extend(
+        stderr_regexes
+            .iter()
+            .map(|regex| (Stream::Stderr, regex.clone())),
+    );
+    patterns.extend(
+        stdout_regexes
+            .iter()
+            .map(|regex| (Stream::Stdout, regex.clone())),
+    );
+    patterns.extend(retry_policy.conditions.iter().filter_map(RetryOn::pattern));
+    let options = RunOptions {
+        pipefail,
+        leftovers,
+        timeout,
+        kill_after,
+        tee: matches.opt_present("tee"),
+        max_output,
+        spill_output: matches.opt_present("spill-output"),
+        patterns,
+        log: log.clone(),
+    };
+    forward_signals();
+    let mut attempts = Vec::new();
+    let run = loop {
+        let attempt_start = Instant::now();
+        let mut run = run_all_cmd(&node, &options)?;
+        let mut attempt = Attempt {
+            status: run.status,
+            signal: run.signal,
+            timed_out: run.timed_out.is_some(),
+            duration: attempt_start.elapsed(),
+            retried_after: None,
+        };
+        let number = attempts.len() as u32 + 1;
+        if valid_status(&run, &error_codes)
+            || number > retry_policy.retries
+            || !retry_policy.retryable(&run)
+        {
+            attempts.push(attempt);
+            break run;
+        }
+        let delay = retry_policy.delay(number);
+        if let Some(log) = &log {
+            log.note(&format!(
+                "attempt {} failed, retrying in {}",
+                number,
+                format_duration(delay)
+            ));
+        }
+        if !retry::wait(delay) {
+            // Interrupted while waiting, the run stops there.
+            run.interrupted = signals::received();
+            attempts.push(attempt);
+            break run;
+        }
+        attempt.retried_after = Some(delay);
+        attempts.push(attempt);
+    };
+    let timing = Timing {
+        splay,
+        duration: start.elapsed(),
+        start_time,
+        end_time: Local::now(),
+    };
+    let killed_leftovers = leftovers == Leftovers::Kill;
+    let report = make_report(
+        cmd_line,
+        &run,
+        &attempts,
+        killed_leftovers,
+        &timing,
+        combined_output,
+    )?;
+    let stderr_matches_regex = stderr_regexes
+        .iter()
+        .any(|regex| run.output_matches(Stream::Stderr, regex));
+    let stdout_matches_regex = stdout_regexes
+        .iter()
+        .any(|regex| run.output_matches(Stream::Stdout, regex));
 
-    if let Some(file) = output_file {
-        let mut file = File::create(file)?;
-        file.write_all(&report)?;
+    if let Some(log) = log {
+        log.finish(&report)?;
     }
 
-    if ((run.status != Some(0) && !error_codes.contains(&run.status.unwrap().to_string()))
-        || !run.stderr.is_empty())
-        && (!stderr_matches_regex && !stdout_matches_regex)
+    if run.timed_out.is_some()
+        || run.interrupted.is_some()
+        || ((!valid_status(&run, &error_codes)
+            || !run.stderr.is_empty()
+            || !run.leftovers.is_empty())
+            && (!stderr_matches_regex && !stdout_matches_regex))
     {
         println!("{}", String::from_utf8_lossy(&report));
     }
@@ -123,189 +540,118 @@ fn main() -> Result<(), io::Error> {
     Ok(())
 }
 
-fn print_usage(program: &str, opts: &Options) {
-    let brief = format!("Usage: {} [options] \"cmd <cmd_args>\"", program);
-    print!("{}", opts.usage(&brief));
-}
-
-fn handle_cmd_output(cmd_return: &mut CmdReturn, output: &mut Output) {
-    cmd_return.status = output.status.code();
-    cmd_return.signal = output.status.signal();
-    cmd_return.stderr.append(&mut output.stderr);
-    cmd_return.stdout.append(&mut output.stdout);
+/// Whether a run ended well: with a zero status or one of `error_codes`,
+/// without timing out or being interrupted.
+fn valid_status(run: &CmdReturn, error_codes: &[String]) -> bool {
+    match run.status {
+        Some(_) if run.timed_out.is_some() || run.interrupted.is_some() => false,
+        Some(status) => status == 0 || error_codes.contains(&status.to_string()),
+        None => false,
+    }
 }
 
-fn handle_cmd_error(
-    cmd_return: &mut CmdReturn,
-    cmd: &str,
-    error: io::Error,
-) -> Result<(), io::Error> {
-    match error.kind() {
-        ErrorKind::NotFound => {
-            let error_line = format!("nrbt: command not found: {}", cmd);
-            cmd_return.status = Some(127);
-            cmd_return.signal = None;
-            cmd_return
-                .stderr
-                .append(&mut error_line.as_bytes().to_vec());
-        }
-        ErrorKind::PermissionDenied => {
-            let error_line = format!("nrbt: permission denied: {}", cmd);
-            cmd_return.status = Some(126);
-            cmd_return.signal = None;
-            cmd_return
-                .stderr
-                .append(&mut error_line.as_bytes().to_vec());
+/// The regexes given to a regex option, exiting when one is not valid.
+fn regexes_opt(matches: &getopts::Matches, name: &str) -> Vec<Regex> {
+    let mut regexes = Vec::new();
+    for expr in matches.opt_strs(name) {
+        match Regex::new(&expr) {
+            Ok(regex) => regexes.push(regex),
+            Err(error) => {
+                eprintln!("nrbt: invalid regex `{}` for -{}: {}", expr, name, error);
+                process::exit(2);
+            }
         }
-        _ => return Err(error),
     }
-    Ok(())
+    regexes
 }
 
-fn run_cmd(
-    cmds: &[Cmd],
-    indice_current: usize,
-    cmd_return: &mut CmdReturn,
-    mut child: Option<Child>,
-) -> Result<(Run, Option<Child>), io::Error> {
-    let cmd_current = &cmds[indice_current];
-    let cmd_line = cmd_current.cmd_line;
-    let mut cmd: Vec<&str> = cmd_line.split_whitespace().collect();
-    let args = cmd.split_off(1);
-    if cmd_current.kind == CmdKind::Pipe {
-        let child_new = if let Some(child) = child {
-            Command::new(cmd[0])
-                .args(&args)
-                .stdin(child.stdout.unwrap())
-                .stdout(Stdio::piped())
-                .spawn()
-        } else {
-            Command::new(cmd[0])
-                .args(&args)
-                .stdout(Stdio::piped())
-                .spawn()
-        };
-
-        child = match child_new {
-            Ok(child) => Some(child),
-            Err(error) => {
-                handle_cmd_error(cmd_return, &cmd[0], error)?;
-                None
-            }
-        };
-    } else {
-        let output = Command::new(cmd[0]).args(&args).output();
-        if indice_current > 0 {
-            let cmd_last = &cmds[indice_current - 1];
-            match cmd_last.kind {
-                CmdKind::SemiCol => {
-                    match output {
-                        Ok(mut output) => handle_cmd_output(cmd_return, &mut output),
-                        Err(error) => handle_cmd_error(cmd_return, &cmd[0], error)?,
-                    };
-                }
-                CmdKind::And => {
-                    if let Some(1) = &cmd_return.status {
-                        return Ok((Run::Abort, None));
-                    } else {
-                        match output {
-                            Ok(mut output) => handle_cmd_output(cmd_return, &mut output),
-                            Err(error) => handle_cmd_error(cmd_return, &cmd[0], error)?,
-                        };
-                    }
-                }
-                CmdKind::Pipe => {
-                    let output = if let Some(child) = child {
-                        Command::new(cmd[0])
-                            .args(&args)
-                            .stdin(child.stdout.unwrap())
-                            .output()
-                    } else {
-                        Command::new(cmd[0]).args(&args).output()
-                    };
-                    child = None;
-                    match output {
-                        Ok(mut output) => handle_cmd_output(cmd_return, &mut output),
-                        Err(error) => handle_cmd_error(cmd_return, &cmd[0], error)?,
-                    };
-                }
-                _ => panic!("Not supported!"),
-            }
-        } else {
-            match output {
-                Ok(mut output) => handle_cmd_output(cmd_return, &mut output),
-                Err(error) => handle_cmd_error(cmd_return, &cmd[0], error)?,
-            };
+/// The value of a duration option, exiting when it is not valid.
+fn duration_opt(matches: &getopts::Matches, name: &str) -> Option<Duration> {
+    let value = matches.opt_str(name)?;
+    match parse_duration(&value) {
+        Some(duration) => Some(duration),
+        None => {
+            eprintln!("nrbt: invalid duration `{}` for --{}", value, name);
+            process::exit(2);
         }
     }
+}
 
-    Ok((Run::Continue, child))
+fn print_usage(program: &str, opts: &Options) {
+    let brief = format!(
+        "Usage: {0} [options] \"cmd <cmd_args>\"\n       {0} [options] -- cmd [cmd_args]",
+        program
+    );
+    print!("{}", opts.usage(&brief));
 }
 
-fn run_all_cmd(cmds: Vec<Cmd>) -> Result<CmdReturn, io::Error> {
-    let mut cmd_return = CmdReturn {
-        status: None,
-        signal: None,
-        stderr: [].to_vec(),
-        stdout: [].to_vec(),
-    };
-    let mut child: Option<Child> = None;
+/// Whether the report shows the output of the steps merged in the order it
+/// was written.
+#[derive(Clone, Copy, PartialEq)]
+enum CombinedOutput {
+    Off,
+    Plain,
+    /// With when each line was written, and on which stream.
+    Timestamped,
+}
 
-    for (i, _) in cmds.iter().enumerate() {
-        let (run, child_new) = run_cmd(&cmds, i, &mut cmd_return, child)?;
-        child = child_new;
-        if run == Run::Abort {
-            break;
-        }
+/// Split the captured output of a step into lines, in the order they were
+/// written. Each line comes with when its first byte was read and its stream.
+fn combined_lines(chunks: &[Chunk]) -> Vec<(Duration, Stream, String)> {
+    let mut lines = Vec::new();
+    let mut splitters = [
+        (Stream::Stdout, LineSplitter::new()),
+        (Stream::Stderr, LineSplitter::new()),
+    ];
+    for chunk in chunks {
+        let (stream, splitter) = match chunk.stream {
+            Stream::Stdout => &mut splitters[0],
+            Stream::Stderr => &mut splitters[1],
+        };
+        splitter.push(chunk.offset, &chunk.data, |start, line| {
+            lines.push((start, *stream, String::from_utf8_lossy(line).into_owned()))
+        });
+    }
+    for (stream, splitter) in splitters {
+        splitter.finish(|start, line| {
+            lines.push((start, stream, String::from_utf8_lossy(line).into_owned()))
+        });
     }
+    lines.sort_by_key(|(start, _, _)| *start);
+    lines
+}
 
-    Ok(cmd_return)
+/// When the command ran.
+struct Timing {
+    /// The random delay waited for before running the command.
+    splay: Option<Duration>,
+    duration: Duration,
+    start_time: DateTime<Local>,
+    end_time: DateTime<Local>,
 }
 
-fn parse_cmd_line(cmd_line: &str) -> Vec<Cmd> {
-    let cmd_line_re = Regex::new(r"\s*([^&{2}|;|\|]+)(&{2}|;|\|)?").unwrap();
-
-    cmd_line_re
-        .captures_iter(cmd_line)
-        .filter_map(|cap| {
-            let cmd = cap.get(1);
-            let separator = cap.get(2);
-            if let Some(cmd) = cmd {
-                if let Some(separator) = separator {
-                    match separator.as_str() {
-                        "&&" => Some(Cmd {
-                            kind: CmdKind::And,
-                            cmd_line: cmd.as_str(),
-                        }),
-                        "|" => Some(Cmd {
-                            kind: CmdKind::Pipe,
-                            cmd_line: cmd.as_str(),
-                        }),
-                        ";" => Some(Cmd {
-                            kind: CmdKind::SemiCol,
-                            cmd_line: cmd.as_str(),
-                        }),
-                        _ => None,
-                    }
-                } else {
-                    Some(Cmd {
-                        kind: CmdKind::Single,
-                        cmd_line: cmd.as_str(),
-                    })
-                }
-            } else {
-                None
-            }
-        })
-        .collect()
+/// The report of a run skipped because another run held the lock.
+fn make_busy_report(cmd_line: &str, busy: &Busy) -> Result<Vec<u8>, io::Error> {
+    let mut buf: Vec<u8> = Vec::new();
+    writeln!(buf, "Run of command: \"{}\"", cmd_line)?;
+    write!(buf, "\nNot run: lock {} is held", busy.path.display())?;
+    if let Some(pid) = busy.pid {
+        write!(buf, " by process {}", pid)?;
+    }
+    match busy.waited {
+        Some(waited) => writeln!(buf, ", after waiting {}", format_duration(waited))?,
+        None => writeln!(buf)?,
+    }
+    Ok(buf)
 }
 
 fn make_report(
     cmd_line: String,
     cmd_return: &CmdReturn,
-    duration: &Duration,
-    start_time: DateTime<Local>,
-    end_time: DateTime<Local>,
+    attempts: &[Attempt],
+    killed_leftovers: bool,
+    timing: &Timing,
+    combined_output: CombinedOutput,
 ) -> Result<Vec<u8>, io::Error> {
     let mut buf: Vec<u8> = Vec::new();
     writeln!(buf, "Run of command: \"{}\"", cmd_line)?;
@@ -319,17 +665,115 @@ fn make_report(
         }
     }
 
-    writeln!(buf, "\nDuration: {} seconds", duration.as_secs())?;
-    writeln!(buf, "Started at: {}", start_time.to_rfc2822())?;
-    writeln!(buf, "Ended at: {}", end_time.to_rfc2822())?;
+    if let Some(timed_out) = &cmd_return.timed_out {
+        write!(
+            buf,
+            "\nTimed out after {}: sent SIGTERM",
+            format_duration(timed_out.after)
+        )?;
+        match timed_out.killed_after {
+            Some(grace) => writeln!(buf, ", then SIGKILL {} later", format_duration(grace))?,
+            None => writeln!(buf)?,
+        }
+    }
 
-    writeln!(buf, "\nStdout")?;
-    writeln!(buf, "------")?;
-    writeln!(buf, "{}", String::from_utf8_lossy(&cmd_return.stdout))?;
+    if attempts.len() > 1 {
+        writeln!(buf, "\nAttempts:")?;
+        for (i, attempt) in attempts.iter().enumerate() {
+            let mut line = match (attempt.status, attempt.signal) {
+                (Some(status), _) => format!("exit code {}", status),
+                (None, Some(signal)) => format!("terminated by signal {}", signal),
+                (None, None) => "not run".to_string(),
+            };
+            if attempt.timed_out {
+                line.push_str(", timed out");
+            }
+            write!(
+                buf,
+                "  {}: {}, {:.3} seconds",
+                i + 1,
+                line,
+                attempt.duration.as_secs_f64()
+            )?;
+            match attempt.retried_after {
+                Some(delay) => writeln!(buf, ", retried after {}", format_duration(delay))?,
+                None => writeln!(buf)?,
+            }
+        }
+    }
 
-    writeln!(buf, "Stderr")?;
-    writeln!(buf, "------")?;
-    writeln!(buf, "{}", String::from_utf8_lossy(&cmd_return.stderr))?;
+    if let Some(signal) = cmd_return.interrupted {
+        writeln!(
+            buf,
+            "\nInterrupted by {}: forwarded to the running job",
+            signal_name(signal)
+        )?;
+    }
+
+    if !cmd_return.leftovers.is_empty() {
+        if killed_leftovers {
+            writeln!(buf, "\nLeftover processes, terminated:")?;
+        } else {
+            writeln!(buf, "\nLeftover processes, still running:")?;
+        }
+        for leftover in &cmd_return.leftovers {
+            writeln!(buf, "  {} {}", leftover.pid, leftover.cmdline)?;
+        }
+    }
+
+    writeln!(buf, "\nDuration: {} seconds", timing.duration.as_secs())?;
+    if let Some(splay) = timing.splay {
+        writeln!(
+            buf,
+            "Splay: {:.3} seconds, waited before starting",
+            splay.as_secs_f64()
+        )?;
+    }
+    writeln!(buf, "Started at: {}", timing.start_time.to_rfc2822())?;
+    writeln!(buf, "Ended at: {}", timing.end_time.to_rfc2822())?;
+
+    for (i, step) in cmd_return.steps.iter().enumerate() {
+        let title = if step.substitution {
+            format!("Step {} (substitution): {}", i + 1, quote_argv(&step.argv))
+        } else {
+            format!("Step {}: {}", i + 1, quote_argv(&step.argv))
+        };
+        writeln!(buf, "\n{}", title)?;
+        writeln!(buf, "{}", "=".repeat(title.chars().count()))?;
+
+        match (step.status, step.signal) {
+            (Some(status), _) => writeln!(buf, "Exit code: {}", status)?,
+            (None, Some(signal)) => writeln!(buf, "Terminated by signal: {}", signal)?,
+            (None, None) => {}
+        }
+        writeln!(buf, "Duration: {:.3} seconds", step.duration.as_secs_f64())?;
+
+        writeln!(buf, "\nStdout")?;
+        writeln!(buf, "------")?;
+        if let Some(redirect) = &step.stdout_redirect {
+            writeln!(buf, "({})", redirect)?;
+        }
+        writeln!(buf, "{}", String::from_utf8_lossy(&step.stdout))?;
+
+        writeln!(buf, "Stderr")?;
+        writeln!(buf, "------")?;
+        if let Some(redirect) = &step.stderr_redirect {
+            writeln!(buf, "({})", redirect)?;
+        }
+        writeln!(buf, "{}", String::from_utf8_lossy(&step.stderr))?;
+
+        if combined_output != CombinedOutput::Off {
+            writeln!(buf, "Combined output")?;
+            writeln!(buf, "---------------")?;
+            for (offset, stream, line) in combined_lines(&step.chunks) {
+                if combined_output == CombinedOutput::Timestamped {
+                    write!(buf, "[+{:.3}s {}] ", offset.as_secs_f64(), stream.name())?;
+                }
+                writeln!(buf, "{}", line)?;
+            }
+            writeln!(buf)?;
+        }
+    }
 
     Ok(buf)
 }
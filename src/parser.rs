@@ -0,0 +1,987 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+use std::vec;
+
+/// A parsed command line: pipelines combined with `&&`, `||` and `;`, the
+/// first two binding tighter than the last one.
+#[derive(Clone)]
+pub enum Node {
+    /// Nodes run one after the other. Empty for an empty command
+    /// substitution.
+    List(Vec<Node>),
+    /// The right node only runs when the left one succeeded.
+    And(Box<Node>, Box<Node>),
+    /// The right node only runs when the left one failed.
+    Or(Box<Node>, Box<Node>),
+    Pipeline(Pipeline),
+}
+
+/// Stages connected by `|`. A leading `!` negates the status of the whole
+/// pipeline.
+#[derive(Clone)]
+pub struct Pipeline {
+    pub negate: bool,
+    pub stages: Vec<Stage>,
+}
+
+#[derive(Clone)]
+pub enum Stage {
+    Cmd(Cmd),
+    /// `( ... )`, run with its own working directory and environment.
+    Subshell(Box<Node>, Vec<Redirect>),
+    /// `{ ...; }`, run in the current working directory and environment.
+    Group(Box<Node>, Vec<Redirect>),
+}
+
+/// A simple command. It may have no argv when it only assigns variables.
+#[derive(Clone)]
+pub struct Cmd {
+    pub assignments: Vec<(String, Word)>,
+    pub argv: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A word of the command line, made of parts that are expanded then joined
+/// when the command is run.
+#[derive(Clone, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+#[derive(Clone)]
+pub enum WordPart {
+    Literal(String),
+    Quoted(String),
+    Var { name: String, quoted: bool },
+    CmdSubst { node: Box<Node>, quoted: bool },
+}
+
+/// A redirection of one of the standard file descriptors of a command,
+/// applied in the order they appear on the command line.
+#[derive(Clone)]
+pub struct Redirect {
+    pub fd: i32,
+    pub kind: RedirectKind,
+}
+
+#[derive(Clone)]
+pub enum RedirectKind {
+    Input(Word),
+    Output(Word),
+    Append(Word),
+    Dup(i32),
+}
+
+#[derive(Clone, Copy)]
+enum RedirectOp {
+    Input,
+    Output,
+    Append,
+    DupInput,
+    DupOutput,
+}
+
+impl RedirectOp {
+    fn as_str(self) -> &'static str {
+        match self {
+            RedirectOp::Input => "<",
+            RedirectOp::Output => ">",
+            RedirectOp::Append => ">>",
+            RedirectOp::DupInput => "<&",
+            RedirectOp::DupOutput => ">&",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    EmptyCmdLine,
+    UnterminatedQuote(char),
+    TrailingBackslash,
+    UnsupportedOperator(&'static str),
+    MisplacedBang,
+    MissingCmd(&'static str),
+    UnexpectedEnd(&'static str),
+    MissingTarget(&'static str),
+    BadFd(String),
+    RedirectWithoutCmd,
+    Unexpected(String),
+    Unclosed(&'static str),
+    PipedGroup,
+    UnterminatedBrace,
+    BadSubstitution(String),
+    UnterminatedSubst,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::EmptyCmdLine => write!(f, "empty command line"),
+            ParseErrorKind::UnterminatedQuote('\'') => write!(f, "unterminated single quote"),
+            ParseErrorKind::UnterminatedQuote('`') => write!(f, "unterminated backquote"),
+            ParseErrorKind::UnterminatedQuote(_) => write!(f, "unterminated double quote"),
+            ParseErrorKind::TrailingBackslash => write!(f, "backslash at end of command line"),
+            ParseErrorKind::UnsupportedOperator(op) => write!(f, "unsupported operator `{}`", op),
+            ParseErrorKind::MisplacedBang => write!(f, "misplaced `!`"),
+            ParseErrorKind::MissingCmd(op) => write!(f, "missing command before `{}`", op),
+            ParseErrorKind::UnexpectedEnd(op) => {
+                write!(f, "unexpected end of command line after `{}`", op)
+            }
+            ParseErrorKind::MissingTarget(op) => write!(f, "missing target after `{}`", op),
+            ParseErrorKind::BadFd(fd) => write!(f, "unsupported file descriptor `{}`", fd),
+            ParseErrorKind::RedirectWithoutCmd => write!(f, "redirection without a command"),
+            ParseErrorKind::Unexpected(token) => write!(f, "unexpected `{}`", token),
+            ParseErrorKind::Unclosed(group) => write!(f, "missing `{}`", group),
+            ParseErrorKind::PipedGroup => write!(f, "subshells and groups cannot be piped"),
+            ParseErrorKind::UnterminatedBrace => write!(f, "unterminated `${{`"),
+            ParseErrorKind::BadSubstitution(expr) => write!(f, "bad substitution `${{{}}}`", expr),
+            ParseErrorKind::UnterminatedSubst => write!(f, "unterminated `$(`"),
+        }
+    }
+}
+
+/// An error in a command line, along with the byte offset in the command
+/// line where it was found.
+#[derive(Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub offset: usize,
+}
+
+impl ParseErrorKind {
+    fn at(self, offset: usize) -> ParseError {
+        ParseError { kind: self, offset }
+    }
+}
+
+impl ParseError {
+    /// Render the error followed by the line of `cmd_line` it is on, with a
+    /// caret under the offending character.
+    pub fn diagnostic(&self, cmd_line: &str) -> String {
+        let start = cmd_line[..self.offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = cmd_line[self.offset..]
+            .find('\n')
+            .map_or(cmd_line.len(), |i| self.offset + i);
+        // Keep tabs so that the caret lines up with the character above it.
+        let padding: String = cmd_line[start..self.offset]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!(
+            "{} (at byte {})\n  {}\n  {}^",
+            self,
+            self.offset,
+            &cmd_line[start..end],
+            padding
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Pipe,
+    And,
+    Or,
+    SemiCol,
+}
+
+impl Op {
+    fn as_str(self) -> &'static str {
+        match self {
+            Op::Pipe => "|",
+            Op::And => "&&",
+            Op::Or => "||",
+            Op::SemiCol => ";",
+        }
+    }
+}
+
+enum Token {
+    Word(Word),
+    Bang,
+    Op(Op),
+    Open,
+    Close,
+    Redirect(i32, RedirectOp),
+}
+
+impl Token {
+    fn as_str(&self) -> String {
+        match self {
+            Token::Word(word) => word.to_string(),
+            Token::Bang => "!".to_string(),
+            Token::Op(op) => op.as_str().to_string(),
+            Token::Open => "(".to_string(),
+            Token::Close => ")".to_string(),
+            Token::Redirect(_, op) => op.as_str().to_string(),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word.as_literal() == Some(keyword))
+    }
+}
+
+impl Word {
+    fn literal(&mut self) -> &mut String {
+        if !matches!(self.parts.last(), Some(WordPart::Literal(_))) {
+            self.parts.push(WordPart::Literal(String::new()));
+        }
+        match self.parts.last_mut() {
+            Some(WordPart::Literal(text)) => text,
+            _ => unreachable!(),
+        }
+    }
+
+    fn quoted(&mut self) -> &mut String {
+        if !matches!(self.parts.last(), Some(WordPart::Quoted(_))) {
+            self.parts.push(WordPart::Quoted(String::new()));
+        }
+        match self.parts.last_mut() {
+            Some(WordPart::Quoted(text)) => text,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The text of the word when it is only made of unquoted literal text.
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(text)] => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Split a leading `NAME=` off the word, if it is an assignment.
+    fn as_assignment(&self) -> Option<(String, Word)> {
+        let text = match self.parts.first() {
+            Some(WordPart::Literal(text)) => text,
+            _ => return None,
+        };
+        let eq = text.find('=')?;
+        if !is_name(&text[..eq]) {
+            return None;
+        }
+
+        let mut value = Word::default();
+        if eq + 1 < text.len() {
+            value.literal().push_str(&text[eq + 1..]);
+        }
+        value.parts.extend(self.parts[1..].iter().cloned());
+        Some((text[..eq].to_string(), value))
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(text) => {
+                    for c in text.chars() {
+                        if "|&;<>()$`\\\"' \t\n".contains(c) {
+                            write!(f, "\\")?;
+                        }
+                        write!(f, "{}", c)?;
+                    }
+                }
+                // Only needed to keep an empty word, like `""`.
+                WordPart::Quoted(text) if text.is_empty() && self.parts.len() > 1 => {}
+                WordPart::Quoted(text) => write!(f, "'{}'", text.replace('\'', "'\\''"))?,
+                WordPart::Var {
+                    name,
+                    quoted: false,
+                } => write!(f, "${{{}}}", name)?,
+                WordPart::Var { name, quoted: true } => write!(f, "\"${{{}}}\"", name)?,
+                WordPart::CmdSubst {
+                    node,
+                    quoted: false,
+                } => write!(f, "$({})", node)?,
+                WordPart::CmdSubst { node, quoted: true } => write!(f, "\"$({})\"", node)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Split a command line into words and operators, following the quoting
+/// rules of the POSIX shell: single quotes preserve everything, double
+/// quotes only let a backslash escape `$`, `` ` ``, `"`, `\` and newline,
+/// and an unquoted backslash preserves the next character.
+///
+/// Tokens are returned along with their byte offset in the command line,
+/// followed by the offset where reading stopped. When `nested` is set, it is
+/// the offset of the `$(` starting a command substitution, and reading stops
+/// at the parenthesis closing it.
+fn tokenize(
+    chars: &mut Peekable<CharIndices>,
+    nested: Option<usize>,
+    len: usize,
+) -> Result<(Vec<(usize, Token)>, usize), ParseError> {
+    let mut tokens = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut word_start = 0;
+    // Subshells opened and not closed yet.
+    let mut depth = 0;
+
+    while let Some((i, c)) = chars.next() {
+        if word.is_none() {
+            word_start = i;
+        }
+        match c {
+            ' ' | '\t' => push_word(&mut tokens, &mut word, word_start),
+            '\n' => {
+                push_word(&mut tokens, &mut word, word_start);
+                if ends_cmd(&tokens) {
+                    tokens.push((i, Token::Op(Op::SemiCol)));
+                }
+            }
+            '#' if word.is_none() => {
+                // A comment runs up to the end of the line.
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            '(' => {
+                push_word(&mut tokens, &mut word, word_start);
+                tokens.push((i, Token::Open));
+                depth += 1;
+            }
+            ')' => {
+                push_word(&mut tokens, &mut word, word_start);
+                if nested.is_some() && depth == 0 {
+                    return Ok((tokens, i));
+                }
+                tokens.push((i, Token::Close));
+                depth -= 1;
+            }
+            '\'' => {
+                let text = word.get_or_insert_with(Word::default).quoted();
+                loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => text.push(c),
+                        None => return Err(ParseErrorKind::UnterminatedQuote('\'').at(i)),
+                    }
+                }
+            }
+            '"' => read_double_quoted(chars, word.get_or_insert_with(Word::default), i, len)?,
+            '`' => read_backquoted(chars, word.get_or_insert_with(Word::default), false, i)?,
+            '\\' => match chars.next() {
+                Some((_, '\n')) => {}
+                Some((_, c)) => word.get_or_insert_with(Word::default).quoted().push(c),
+                None => return Err(ParseErrorKind::TrailingBackslash.at(i)),
+            },
+            '$' => read_dollar(chars, word.get_or_insert_with(Word::default), false, i, len)?,
+            '|' | '&' | ';' => {
+                push_word(&mut tokens, &mut word, word_start);
+                tokens.push((i, Token::Op(read_operator(chars, c, i)?)));
+            }
+            '<' | '>' => {
+                // Digits right before the operator are the redirected fd.
+                let digits = word.as_ref().and_then(Word::as_literal);
+                let (fd, start) = match digits {
+                    Some(digits) if digits.chars().all(|c| c.is_ascii_digit()) => {
+                        let fd = parse_fd(digits, word_start)?;
+                        word = None;
+                        (Some(fd), word_start)
+                    }
+                    _ => {
+                        push_word(&mut tokens, &mut word, word_start);
+                        (None, i)
+                    }
+                };
+                let op = read_redirect_op(chars, c);
+                let fd = fd.unwrap_or(if c == '<' { 0 } else { 1 });
+                tokens.push((start, Token::Redirect(fd, op)));
+            }
+            c => word.get_or_insert_with(Word::default).literal().push(c),
+        }
+    }
+
+    if let Some(start) = nested {
+        return Err(ParseErrorKind::UnterminatedSubst.at(start));
+    }
+    push_word(&mut tokens, &mut word, word_start);
+
+    Ok((tokens, len))
+}
+
+/// Whether a newline after `tokens` separates commands like `;`. It is only
+/// a blank after an operator, an opening parenthesis or brace, or an empty
+/// line, where a command has to follow.
+fn ends_cmd(tokens: &[(usize, Token)]) -> bool {
+    let opens_group = |token: &Token| token.is_keyword("{");
+    match tokens {
+        [.., (_, Token::Close)] => true,
+        [(_, last)] if opens_group(last) => false,
+        [.., (_, before), (_, last)] if opens_group(last) => {
+            !matches!(before, Token::Op(_) | Token::Open | Token::Bang)
+        }
+        [.., (_, Token::Word(_))] => true,
+        _ => false,
+    }
+}
+
+/// Terminate the word being read, if any. An unquoted `!` word is kept apart
+/// as it negates the pipeline when it comes first.
+fn push_word(tokens: &mut Vec<(usize, Token)>, word: &mut Option<Word>, start: usize) {
+    match word.take() {
+        Some(word) if word.as_literal() == Some("!") => tokens.push((start, Token::Bang)),
+        Some(word) => tokens.push((start, Token::Word(word))),
+        None => {}
+    }
+}
+
+fn read_double_quoted(
+    chars: &mut Peekable<CharIndices>,
+    word: &mut Word,
+    start: usize,
+    len: usize,
+) -> Result<(), ParseError> {
+    // Make sure `""` yields an empty word.
+    word.quoted();
+    loop {
+        match chars.next() {
+            Some((_, '"')) => return Ok(()),
+            Some((_, '\\')) => match chars.next() {
+                Some((_, '\n')) => {}
+                Some((_, c @ '$')) | Some((_, c @ '`')) | Some((_, c @ '"'))
+                | Some((_, c @ '\\')) => word.quoted().push(c),
+                Some((_, c)) => {
+                    word.quoted().push('\\');
+                    word.quoted().push(c);
+                }
+                None => return Err(ParseErrorKind::UnterminatedQuote('"').at(start)),
+            },
+            Some((i, '$')) => read_dollar(chars, word, true, i, len)?,
+            Some((i, '`')) => read_backquoted(chars, word, true, i)?,
+            Some((_, c)) => word.quoted().push(c),
+            None => return Err(ParseErrorKind::UnterminatedQuote('"').at(start)),
+        }
+    }
+}
+
+/// Read the old-style `` `...` `` command substitution, in which a backslash
+/// only escapes `$`, `` ` ``, `\` and, inside double quotes, `"`. As its
+/// content is parsed on its own, errors in it are reported at `start`.
+fn read_backquoted(
+    chars: &mut Peekable<CharIndices>,
+    word: &mut Word,
+    quoted: bool,
+    start: usize,
+) -> Result<(), ParseError> {
+    let mut cmd_line = String::new();
+    loop {
+        match chars.next() {
+            Some((_, '`')) => break,
+            Some((_, '\\')) => match chars.next() {
+                Some((_, c @ '$')) | Some((_, c @ '`')) | Some((_, c @ '\\')) => cmd_line.push(c),
+                Some((_, '"')) if quoted => cmd_line.push('"'),
+                Some((_, c)) => {
+                    cmd_line.push('\\');
+                    cmd_line.push(c);
+                }
+                None => return Err(ParseErrorKind::UnterminatedQuote('`').at(start)),
+            },
+            Some((_, c)) => cmd_line.push(c),
+            None => return Err(ParseErrorKind::UnterminatedQuote('`').at(start)),
+        }
+    }
+
+    let mut chars = cmd_line.char_indices().peekable();
+    let node = tokenize(&mut chars, None, cmd_line.len())
+        .and_then(|(tokens, end)| parse_tokens(tokens, end, true))
+        .map_err(|error| error.kind.at(start))?;
+    let node = Box::new(node);
+    word.parts.push(WordPart::CmdSubst { node, quoted });
+    Ok(())
+}
+
+/// Read what follows a `$`: a `$(...)` command substitution or a parameter,
+/// either `$NAME`, `${NAME}` or `$$`. A `$` not followed by any of them is
+/// taken literally.
+fn read_dollar(
+    chars: &mut Peekable<CharIndices>,
+    word: &mut Word,
+    quoted: bool,
+    start: usize,
+    len: usize,
+) -> Result<(), ParseError> {
+    let name = match chars.peek().map(|&(_, c)| c) {
+        Some('(') => {
+            chars.next();
+            if chars.peek().map(|&(_, c)| c) == Some('(') {
+                return Err(ParseErrorKind::UnsupportedOperator("$((").at(start));
+            }
+            let (tokens, end) = tokenize(chars, Some(start), len)?;
+            let node = Box::new(parse_tokens(tokens, end, true)?);
+            word.parts.push(WordPart::CmdSubst { node, quoted });
+            return Ok(());
+        }
+        Some('{') => {
+            chars.next();
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '}')) => break,
+                    Some((_, c)) => name.push(c),
+                    None => return Err(ParseErrorKind::UnterminatedBrace.at(start)),
+                }
+            }
+            if !is_name(&name) && name != "$" {
+                return Err(ParseErrorKind::BadSubstitution(name).at(start));
+            }
+            name
+        }
+        Some('$') => {
+            chars.next();
+            "$".to_string()
+        }
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            let mut name = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !c.is_ascii_alphanumeric() && c != '_' {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            name
+        }
+        _ => {
+            if quoted {
+                word.quoted().push('$');
+            } else {
+                word.literal().push('$');
+            }
+            return Ok(());
+        }
+    };
+
+    word.parts.push(WordPart::Var { name, quoted });
+    Ok(())
+}
+
+fn read_operator(
+    chars: &mut Peekable<CharIndices>,
+    first: char,
+    start: usize,
+) -> Result<Op, ParseError> {
+    let doubled = chars.peek().map(|&(_, c)| c) == Some(first);
+    match (first, doubled) {
+        ('|', false) => Ok(Op::Pipe),
+        ('|', true) => {
+            chars.next();
+            Ok(Op::Or)
+        }
+        ('&', true) => {
+            chars.next();
+            Ok(Op::And)
+        }
+        ('&', false) => Err(ParseErrorKind::UnsupportedOperator("&").at(start)),
+        _ => Ok(Op::SemiCol),
+    }
+}
+
+fn read_redirect_op(chars: &mut Peekable<CharIndices>, first: char) -> RedirectOp {
+    let next = chars.peek().map(|&(_, c)| c);
+    let op = match (first, next) {
+        ('<', Some('&')) => RedirectOp::DupInput,
+        ('<', _) => return RedirectOp::Input,
+        (_, Some('>')) => RedirectOp::Append,
+        (_, Some('&')) => RedirectOp::DupOutput,
+        // `>|` only differs from `>` in shells where noclobber is set.
+        (_, Some('|')) => RedirectOp::Output,
+        _ => return RedirectOp::Output,
+    };
+    chars.next();
+    op
+}
+
+/// Only the standard file descriptors can be redirected.
+fn parse_fd(digits: &str, start: usize) -> Result<i32, ParseError> {
+    match digits.parse() {
+        Ok(fd) if fd <= 2 => Ok(fd),
+        _ => Err(ParseErrorKind::BadFd(digits.to_string()).at(start)),
+    }
+}
+
+/// Parse a command line into the tree of commands to run.
+pub fn parse_cmd_line(cmd_line: &str) -> Result<Node, ParseError> {
+    let mut chars = cmd_line.char_indices().peekable();
+    let (tokens, end) = tokenize(&mut chars, None, cmd_line.len())?;
+    parse_tokens(tokens, end, false)
+}
+
+/// The tree of a single command running `argv` as is, without any parsing
+/// or expansion.
+pub fn argv_cmd(argv: &[String]) -> Node {
+    let argv = argv
+        .iter()
+        .map(|arg| Word {
+            parts: vec![WordPart::Quoted(arg.clone())],
+        })
+        .collect();
+    Node::Pipeline(Pipeline {
+        negate: false,
+        stages: vec![Stage::Cmd(Cmd {
+            assignments: Vec::new(),
+            argv,
+            redirects: Vec::new(),
+        })],
+    })
+}
+
+/// Build the tree of commands from the tokens of a command line. Only the
+/// command line of a command substitution may be empty. `end` is the offset
+/// of the end of the tokens.
+fn parse_tokens(
+    tokens: Vec<(usize, Token)>,
+    end: usize,
+    allow_empty: bool,
+) -> Result<Node, ParseError> {
+    let mut parser = Parser {
+        tokens: tokens.into_iter().peekable(),
+        end,
+    };
+    let node = parser.parse_list()?;
+    let offset = parser.offset();
+    if let Some(token) = parser.next() {
+        return Err(ParseErrorKind::Unexpected(token.as_str()).at(offset));
+    }
+    match node {
+        Node::List(nodes) if nodes.is_empty() && !allow_empty => {
+            Err(ParseErrorKind::EmptyCmdLine.at(0))
+        }
+        node => Ok(node),
+    }
+}
+
+/// A recursive descent parser for the grammar:
+///
+/// ```text
+/// list     := [and_or (";" and_or)* [";"]]
+/// and_or   := pipeline (("&&" | "||") pipeline)*
+/// pipeline := "!"* stage ("|" stage)*
+/// stage    := "(" list ")" redirect* | "{" list "}" redirect* | cmd
+/// cmd      := assignment* (word | redirect)*
+/// ```
+struct Parser {
+    tokens: Peekable<vec::IntoIter<(usize, Token)>>,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek().map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.next().map(|(_, token)| token)
+    }
+
+    /// The offset of the next token, or of the end of the tokens.
+    fn offset(&mut self) -> usize {
+        self.tokens.peek().map_or(self.end, |&(offset, _)| offset)
+    }
+
+    /// Parse commands separated by `;` up to the end of the command line or
+    /// of the enclosing group.
+    fn parse_list(&mut self) -> Result<Node, ParseError> {
+        let mut nodes = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) => break,
+                Some(token) if token.is_keyword("}") => break,
+                _ => {}
+            }
+            nodes.push(self.parse_and_or()?);
+            match self.peek() {
+                Some(Token::Op(Op::SemiCol)) => self.next(),
+                _ => break,
+            };
+        }
+
+        if nodes.len() == 1 {
+            Ok(nodes.remove(0))
+        } else {
+            Ok(Node::List(nodes))
+        }
+    }
+
+    fn parse_and_or(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.parse_pipeline()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op)) if *op == Op::And || *op == Op::Or => *op,
+                _ => return Ok(node),
+            };
+            self.next();
+            if self.peek().is_none() {
+                return Err(ParseErrorKind::UnexpectedEnd(op.as_str()).at(self.end));
+            }
+            let right = Box::new(self.parse_pipeline()?);
+            node = match op {
+                Op::And => Node::And(Box::new(node), right),
+                _ => Node::Or(Box::new(node), right),
+            };
+        }
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Node, ParseError> {
+        let start = self.offset();
+        let mut negate = false;
+        while let Some(Token::Bang) = self.peek() {
+            self.next();
+            negate = !negate;
+        }
+        let stage_follows = matches!(
+            self.peek(),
+            Some(Token::Word(_)) | Some(Token::Open) | Some(Token::Redirect(..))
+        );
+        if negate && !stage_follows {
+            return Err(ParseErrorKind::MisplacedBang.at(start));
+        }
+
+        let mut stages = vec![self.parse_stage()?];
+        while let Some(Token::Op(Op::Pipe)) = self.peek() {
+            self.next();
+            let offset = self.offset();
+            match self.peek() {
+                None => return Err(ParseErrorKind::UnexpectedEnd("|").at(offset)),
+                Some(Token::Bang) => return Err(ParseErrorKind::MisplacedBang.at(offset)),
+                _ => stages.push(self.parse_stage()?),
+            }
+        }
+
+        if stages.len() > 1 && stages.iter().any(|stage| !matches!(stage, Stage::Cmd(_))) {
+            return Err(ParseErrorKind::PipedGroup.at(start));
+        }
+        Ok(Node::Pipeline(Pipeline { negate, stages }))
+    }
+
+    fn parse_stage(&mut self) -> Result<Stage, ParseError> {
+        let start = self.offset();
+        let kind = match self.peek() {
+            Some(Token::Open) => None,
+            Some(token) if token.is_keyword("{") => None,
+            Some(token) if token.is_keyword("}") => Some(ParseErrorKind::MissingCmd("}")),
+            Some(Token::Word(_)) | Some(Token::Redirect(..)) => return self.parse_cmd(),
+            Some(Token::Op(op)) => Some(ParseErrorKind::MissingCmd(op.as_str())),
+            Some(Token::Close) => Some(ParseErrorKind::MissingCmd(")")),
+            Some(Token::Bang) => Some(ParseErrorKind::MisplacedBang),
+            None => Some(ParseErrorKind::EmptyCmdLine),
+        };
+        if let Some(kind) = kind {
+            return Err(kind.at(start));
+        }
+        let close = match self.next() {
+            Some(Token::Open) => ")",
+            _ => "}",
+        };
+
+        let body = self.parse_list()?;
+        let offset = self.offset();
+        if matches!(&body, Node::List(nodes) if nodes.is_empty()) {
+            return match self.peek() {
+                // Point at the unclosed parenthesis or brace.
+                None => Err(ParseErrorKind::Unclosed(close).at(start)),
+                _ => Err(ParseErrorKind::MissingCmd(close).at(offset)),
+            };
+        }
+        match self.next() {
+            Some(Token::Close) if close == ")" => {}
+            Some(ref token) if close == "}" && token.is_keyword("}") => {}
+            Some(token) => return Err(ParseErrorKind::Unexpected(token.as_str()).at(offset)),
+            None => return Err(ParseErrorKind::Unclosed(close).at(start)),
+        }
+
+        let mut redirects = Vec::new();
+        while let Some(Token::Redirect(fd, op)) = self.peek() {
+            let (fd, op) = (*fd, *op);
+            self.next();
+            redirects.push(self.parse_redirect(fd, op)?);
+        }
+        let offset = self.offset();
+        match self.peek() {
+            None | Some(Token::Op(_)) | Some(Token::Close) => {}
+            Some(token) if token.is_keyword("}") => {}
+            Some(token) => return Err(ParseErrorKind::Unexpected(token.as_str()).at(offset)),
+        }
+
+        let body = Box::new(body);
+        if close == ")" {
+            Ok(Stage::Subshell(body, redirects))
+        } else {
+            Ok(Stage::Group(body, redirects))
+        }
+    }
+
+    fn parse_cmd(&mut self) -> Result<Stage, ParseError> {
+        let start = self.offset();
+        let mut assignments = Vec::new();
+        let mut argv = Vec::new();
+        let mut redirects = Vec::new();
+
+        loop {
+            let offset = self.offset();
+            match self.peek() {
+                Some(Token::Word(_)) | Some(Token::Bang) | Some(Token::Redirect(..)) => {}
+                Some(Token::Open) => {
+                    return Err(ParseErrorKind::Unexpected("(".to_string()).at(offset));
+                }
+                _ => break,
+            }
+            match self.next() {
+                Some(Token::Word(word)) => match word.as_assignment() {
+                    Some(assignment) if argv.is_empty() => assignments.push(assignment),
+                    _ => argv.push(word),
+                },
+                Some(Token::Redirect(fd, op)) => redirects.push(self.parse_redirect(fd, op)?),
+                _ => {
+                    let mut word = Word::default();
+                    word.literal().push('!');
+                    argv.push(word);
+                }
+            }
+        }
+
+        if argv.is_empty() && !redirects.is_empty() {
+            return Err(ParseErrorKind::RedirectWithoutCmd.at(start));
+        }
+        Ok(Stage::Cmd(Cmd {
+            assignments,
+            argv,
+            redirects,
+        }))
+    }
+
+    fn parse_redirect(&mut self, fd: i32, op: RedirectOp) -> Result<Redirect, ParseError> {
+        let offset = self.offset();
+        let target = match self.next() {
+            Some(Token::Word(word)) => word,
+            Some(Token::Bang) => {
+                let mut word = Word::default();
+                word.literal().push('!');
+                word
+            }
+            _ => return Err(ParseErrorKind::MissingTarget(op.as_str()).at(offset)),
+        };
+        let kind = match op {
+            RedirectOp::Input => RedirectKind::Input(target),
+            RedirectOp::Output => RedirectKind::Output(target),
+            RedirectOp::Append => RedirectKind::Append(target),
+            RedirectOp::DupInput | RedirectOp::DupOutput => match target.as_literal() {
+                Some(fd) if !fd.is_empty() && fd.chars().all(|c| c.is_ascii_digit()) => {
+                    RedirectKind::Dup(parse_fd(fd, offset)?)
+                }
+                _ => return Err(ParseErrorKind::BadFd(target.to_string()).at(offset)),
+            },
+        };
+        Ok(Redirect { fd, kind })
+    }
+}
+
+/// Render an argv back as a command line, quoting the words that would not
+/// be read back as a single word.
+pub fn quote_argv(argv: &[String]) -> String {
+    let words: Vec<String> = argv
+        .iter()
+        .map(|word| {
+            let plain = !word.is_empty()
+                && word
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "%+,-./:=@_^".contains(c));
+            if plain {
+                word.clone()
+            } else {
+                format!("'{}'", word.replace('\'', "'\\''"))
+            }
+        })
+        .collect();
+    words.join(" ")
+}
+
+fn format_redirects(f: &mut fmt::Formatter, redirects: &[Redirect]) -> fmt::Result {
+    for redirect in redirects {
+        let (op, target) = match &redirect.kind {
+            RedirectKind::Input(target) => ("<", target.to_string()),
+            RedirectKind::Output(target) => (">", target.to_string()),
+            RedirectKind::Append(target) => (">>", target.to_string()),
+            RedirectKind::Dup(fd) if redirect.fd == 0 => ("<&", fd.to_string()),
+            RedirectKind::Dup(fd) => (">&", fd.to_string()),
+        };
+        write!(f, " {}{}{}", redirect.fd, op, target)?;
+    }
+    Ok(())
+}
+
+/// Render a command tree back as a command line.
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Node::List(nodes) => {
+                for (i, node) in nodes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", node)?;
+                }
+                Ok(())
+            }
+            Node::And(left, right) => write!(f, "{} && {}", left, right),
+            Node::Or(left, right) => write!(f, "{} || {}", left, right),
+            Node::Pipeline(pipeline) => {
+                if pipeline.negate {
+                    write!(f, "! ")?;
+                }
+                for (i, stage) in pipeline.stages.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", stage)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::Cmd(cmd) => {
+                let mut words: Vec<String> = cmd
+                    .assignments
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                words.extend(cmd.argv.iter().map(Word::to_string));
+                write!(f, "{}", words.join(" "))?;
+                format_redirects(f, &cmd.redirects)
+            }
+            Stage::Subshell(body, redirects) => {
+                write!(f, "({})", body)?;
+                format_redirects(f, redirects)
+            }
+            Stage::Group(body, redirects) => {
+                write!(f, "{{ {}; }}", body)?;
+                format_redirects(f, redirects)
+            }
+        }
+    }
+}
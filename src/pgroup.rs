@@ -0,0 +1,126 @@
+use std::fs;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The process group of the pipeline being run, or 0 between pipelines.
+static CURRENT_GROUP: AtomicI32 = AtomicI32::new(0);
+
+pub fn current_group() -> i32 {
+    CURRENT_GROUP.load(Ordering::SeqCst)
+}
+
+pub fn set_current_group(pgid: i32) {
+    CURRENT_GROUP.store(pgid, Ordering::SeqCst);
+}
+
+/// Send `signal` to the processes of the pipeline being run, if any.
+/// Returns whether there was one.
+pub fn signal_current_group(signal: i32) -> bool {
+    let pgid = CURRENT_GROUP.load(Ordering::SeqCst);
+    if pgid == 0 {
+        return false;
+    }
+    unsafe { libc::kill(-pgid, signal) == 0 }
+}
+
+/// Whether stdin is a terminal with the process group of nrbt in the
+/// foreground, as when run from an interactive shell.
+pub fn owns_terminal() -> bool {
+    unsafe { libc::isatty(0) == 1 && libc::tcgetpgrp(0) == libc::getpgrp() }
+}
+
+/// Put the process group `pgid` in the foreground of the terminal on stdin,
+/// or the one of nrbt when 0, so that it can read from it. SIGTTOU is blocked
+/// meanwhile, as nrbt is not in the foreground anymore when taking the
+/// terminal back.
+pub fn set_foreground(pgid: i32) {
+    unsafe {
+        let pgid = if pgid == 0 { libc::getpgrp() } else { pgid };
+        let mut ttou = mem::zeroed();
+        let mut mask = mem::zeroed();
+        libc::sigemptyset(&mut ttou);
+        libc::sigaddset(&mut ttou, libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &ttou, &mut mask);
+        libc::tcsetpgrp(0, pgid);
+        libc::pthread_sigmask(libc::SIG_SETMASK, &mask, ptr::null_mut());
+    }
+}
+
+/// A process still running in the process group of a pipeline after all of
+/// its commands exited.
+pub struct Leftover {
+    pub pid: i32,
+    pub cmdline: String,
+}
+
+/// List the live processes of a process group, from `/proc`.
+pub fn group_members(pgid: i32) -> Vec<Leftover> {
+    let mut members = Vec::new();
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return members,
+    };
+
+    for entry in entries.flatten() {
+        let pid: i32 = match entry.file_name().to_string_lossy().parse() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        // The fields following the command name, which is in parentheses and
+        // may contain spaces, start with the state, the parent pid and the
+        // process group.
+        let (comm, fields) = match (stat.find('('), stat.rfind(')')) {
+            (Some(start), Some(end)) if start < end => (
+                &stat[start + 1..end],
+                stat[end + 1..].split_whitespace().collect::<Vec<_>>(),
+            ),
+            _ => continue,
+        };
+        if fields.len() < 3 || fields[0] == "Z" || fields[2] != pgid.to_string() {
+            continue;
+        }
+
+        let cmdline = fs::read(entry.path().join("cmdline")).unwrap_or_default();
+        let cmdline: Vec<String> = cmdline
+            .split(|&b| b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+        let cmdline = if cmdline.is_empty() {
+            // Processes being set up have no command line yet.
+            format!("[{}]", comm)
+        } else {
+            cmdline.join(" ")
+        };
+        members.push(Leftover { pid, cmdline });
+    }
+
+    members.sort_by_key(|member| member.pid);
+    members
+}
+
+/// Terminate what remains of a process group: SIGTERM, then SIGKILL when
+/// processes are still there after `grace`.
+pub fn terminate_group(pgid: i32, grace: Duration) {
+    unsafe {
+        libc::kill(-pgid, libc::SIGTERM);
+    }
+    let start = Instant::now();
+    // Zombies are not waited for, as they are reaped by their new parent.
+    while !group_members(pgid).is_empty() {
+        if start.elapsed() >= grace {
+            unsafe {
+                libc::kill(-pgid, libc::SIGKILL);
+            }
+            return;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,465 @@
+use crate::expand::{expand_string, expand_word, expand_words, Context};
+use crate::parser::{quote_argv, Cmd, Node, Redirect, RedirectKind, Stage, Word, WordPart};
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+/// What a command line would run, with its words expanded.
+pub enum Plan {
+    List(Vec<Plan>),
+    And(Box<Plan>, Box<Plan>),
+    Or(Box<Plan>, Box<Plan>),
+    Pipeline {
+        negate: bool,
+        stages: Vec<PlanStage>,
+    },
+}
+
+/// A word of a step, and whether it was expanded. Words containing a command
+/// substitution are kept as written.
+type PlanWord = (String, bool);
+
+/// A stage of a pipeline, with the plans of the command substitutions run
+/// while expanding its words. Steps are numbered in the order they run, like
+/// in the report: the steps of command substitutions come before the step
+/// they are part of, and the stages of a pipeline are all expanded before
+/// any of them runs.
+pub enum PlanStage {
+    Step {
+        number: usize,
+        assignments: Vec<(String, PlanWord)>,
+        argv: Vec<PlanWord>,
+        redirects: Vec<PlanRedirect>,
+        substitutions: Vec<Plan>,
+    },
+    Subshell(Box<Plan>, Vec<PlanRedirect>, Vec<Plan>),
+    Group(Box<Plan>, Vec<PlanRedirect>, Vec<Plan>),
+}
+
+pub struct PlanRedirect {
+    fd: i32,
+    op: &'static str,
+    target: PlanWord,
+}
+
+/// Expands words like the executor would, keeping track of `cd` and of
+/// assignments, but without running anything.
+struct Planner {
+    cwd: PathBuf,
+    vars: HashMap<String, String>,
+    steps: usize,
+    /// The plans of the command substitutions of the stage being planned.
+    substitutions: Vec<Plan>,
+}
+
+impl Context for Planner {
+    fn var(&self, name: &str) -> Option<String> {
+        match self.vars.get(name) {
+            Some(value) => Some(value.clone()),
+            None => env::var_os(name).map(|value| value.to_string_lossy().into_owned()),
+        }
+    }
+
+    fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    fn substitute(&mut self, _node: &Node) -> io::Result<String> {
+        // Words containing a command substitution are not expanded.
+        Ok(String::new())
+    }
+}
+
+fn has_substitution(word: &Word) -> bool {
+    word.parts
+        .iter()
+        .any(|part| matches!(part, WordPart::CmdSubst { .. }))
+}
+
+impl Planner {
+    fn plan_node(&mut self, node: &Node) -> io::Result<Plan> {
+        Ok(match node {
+            Node::List(nodes) => {
+                let mut plans = Vec::new();
+                for node in nodes {
+                    plans.push(self.plan_node(node)?);
+                }
+                Plan::List(plans)
+            }
+            Node::And(left, right) => Plan::And(
+                Box::new(self.plan_node(left)?),
+                Box::new(self.plan_node(right)?),
+            ),
+            Node::Or(left, right) => Plan::Or(
+                Box::new(self.plan_node(left)?),
+                Box::new(self.plan_node(right)?),
+            ),
+            Node::Pipeline(pipeline) => {
+                let alone = pipeline.stages.len() == 1;
+                let mut stages = Vec::new();
+                for stage in &pipeline.stages {
+                    stages.push(self.plan_stage(stage, alone)?);
+                }
+                for stage in &mut stages {
+                    if let PlanStage::Step { number, .. } = stage {
+                        self.steps += 1;
+                        *number = self.steps;
+                    }
+                }
+                Plan::Pipeline {
+                    negate: pipeline.negate,
+                    stages,
+                }
+            }
+        })
+    }
+
+    fn plan_stage(&mut self, stage: &Stage, alone: bool) -> io::Result<PlanStage> {
+        Ok(match stage {
+            Stage::Cmd(cmd) => self.plan_cmd(cmd, alone)?,
+            Stage::Subshell(body, redirects) => {
+                let redirects = self.plan_redirects(redirects)?;
+                let substitutions = mem::take(&mut self.substitutions);
+                let body = self.plan_apart(body)?;
+                PlanStage::Subshell(Box::new(body), redirects, substitutions)
+            }
+            Stage::Group(body, redirects) => {
+                let redirects = self.plan_redirects(redirects)?;
+                let substitutions = mem::take(&mut self.substitutions);
+                PlanStage::Group(Box::new(self.plan_node(body)?), redirects, substitutions)
+            }
+        })
+    }
+
+    /// Plan `node` in a scope of its own, like a subshell or a command
+    /// substitution, which cannot change the one it is part of.
+    fn plan_apart(&mut self, node: &Node) -> io::Result<Plan> {
+        let (cwd, vars) = (self.cwd.clone(), self.vars.clone());
+        let plan = self.plan_node(node);
+        self.cwd = cwd;
+        self.vars = vars;
+        plan
+    }
+
+    /// Plan the command substitutions of `word`, run when it is expanded.
+    fn plan_substitutions(&mut self, word: &Word) -> io::Result<()> {
+        for part in &word.parts {
+            if let WordPart::CmdSubst { node, .. } = part {
+                let outer = mem::take(&mut self.substitutions);
+                let plan = self.plan_apart(node);
+                self.substitutions = outer;
+                self.substitutions.push(plan?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Plan a command, numbered once all the stages of its pipeline are.
+    fn plan_cmd(&mut self, cmd: &Cmd, alone: bool) -> io::Result<PlanStage> {
+        let mut argv = Vec::new();
+        for word in &cmd.argv {
+            if has_substitution(word) {
+                self.plan_substitutions(word)?;
+                argv.push((word.to_string(), false));
+            } else {
+                let fields = expand_words(std::slice::from_ref(word), self)?;
+                argv.extend(fields.into_iter().map(|field| (field, true)));
+            }
+        }
+        let mut assignments = Vec::new();
+        for (name, value) in &cmd.assignments {
+            let value = if has_substitution(value) {
+                self.plan_substitutions(value)?;
+                (value.to_string(), false)
+            } else {
+                (expand_string(value, self)?, true)
+            };
+            assignments.push((name.clone(), value));
+        }
+        let redirects = self.plan_redirects(&cmd.redirects)?;
+
+        if alone && argv.is_empty() {
+            for (name, (value, _)) in &assignments {
+                self.vars.insert(name.clone(), value.clone());
+            }
+        }
+        if let [(cd, true), (dir, true)] = argv.as_slice() {
+            if alone && cd == "cd" {
+                if let Ok(cwd) = self.cwd.join(dir).canonicalize() {
+                    self.cwd = cwd;
+                }
+            }
+        }
+
+        Ok(PlanStage::Step {
+            number: 0,
+            assignments,
+            argv,
+            redirects,
+            substitutions: mem::take(&mut self.substitutions),
+        })
+    }
+
+    fn plan_redirects(&mut self, redirects: &[Redirect]) -> io::Result<Vec<PlanRedirect>> {
+        let mut plans = Vec::new();
+        for redirect in redirects {
+            let (op, target) = match &redirect.kind {
+                RedirectKind::Input(target) => ("<", target),
+                RedirectKind::Output(target) => (">", target),
+                RedirectKind::Append(target) => (">>", target),
+                RedirectKind::Dup(fd) => {
+                    plans.push(PlanRedirect {
+                        fd: redirect.fd,
+                        op: if redirect.fd == 0 { "<&" } else { ">&" },
+                        target: (fd.to_string(), true),
+                    });
+                    continue;
+                }
+            };
+            let fields = if has_substitution(target) {
+                self.plan_substitutions(target)?;
+                Vec::new()
+            } else {
+                expand_word(target, self)?
+            };
+            let target = match fields.as_slice() {
+                [path] => (path.clone(), true),
+                _ => (target.to_string(), false),
+            };
+            plans.push(PlanRedirect {
+                fd: redirect.fd,
+                op,
+                target,
+            });
+        }
+        Ok(plans)
+    }
+}
+
+/// Work out what running `node` would do, without running anything. Command
+/// substitutions cannot be expanded this way, so the words containing them
+/// are kept as written.
+pub fn plan(node: &Node) -> io::Result<Plan> {
+    let mut planner = Planner {
+        cwd: env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        vars: HashMap::new(),
+        steps: 0,
+        substitutions: Vec::new(),
+    };
+    planner.plan_node(node)
+}
+
+fn format_word((word, expanded): &PlanWord) -> String {
+    if *expanded {
+        quote_argv(std::slice::from_ref(word))
+    } else {
+        word.clone()
+    }
+}
+
+fn format_redirects(redirects: &[PlanRedirect]) -> String {
+    let mut text = String::new();
+    for redirect in redirects {
+        let default_fd = if redirect.op.starts_with('<') { 0 } else { 1 };
+        text.push(' ');
+        if redirect.fd != default_fd {
+            text.push_str(&redirect.fd.to_string());
+        }
+        text.push_str(redirect.op);
+        if !redirect.op.ends_with('&') {
+            text.push(' ');
+        }
+        text.push_str(&format_word(&redirect.target));
+    }
+    text
+}
+
+/// Render a plan as an indented tree, one step per line.
+pub fn format_text(plan: &Plan) -> String {
+    let mut text = String::new();
+    write_text(&mut text, plan, 0);
+    text
+}
+
+fn write_text(text: &mut String, plan: &Plan, depth: usize) {
+    let indent = "  ".repeat(depth);
+    match plan {
+        Plan::List(plans) => {
+            let _ = writeln!(text, "{}list", indent);
+            for plan in plans {
+                write_text(text, plan, depth + 1);
+            }
+        }
+        Plan::And(left, right) | Plan::Or(left, right) => {
+            let op = if let Plan::And(..) = plan {
+                "and"
+            } else {
+                "or"
+            };
+            let _ = writeln!(text, "{}{}", indent, op);
+            write_text(text, left, depth + 1);
+            write_text(text, right, depth + 1);
+        }
+        Plan::Pipeline { negate, stages } if *negate || stages.len() > 1 => {
+            let negated = if *negate { " (negated)" } else { "" };
+            let _ = writeln!(text, "{}pipeline{}", indent, negated);
+            for stage in stages {
+                write_stage(text, stage, depth + 1);
+            }
+        }
+        Plan::Pipeline { stages, .. } => {
+            for stage in stages {
+                write_stage(text, stage, depth);
+            }
+        }
+    }
+}
+
+/// Write the plans of command substitutions, under what they are part of.
+fn write_substitutions(text: &mut String, substitutions: &[Plan], depth: usize) {
+    for plan in substitutions {
+        let _ = writeln!(text, "{}substitution", "  ".repeat(depth));
+        write_text(text, plan, depth + 1);
+    }
+}
+
+fn write_stage(text: &mut String, stage: &PlanStage, depth: usize) {
+    let indent = "  ".repeat(depth);
+    match stage {
+        PlanStage::Step {
+            number,
+            assignments,
+            argv,
+            redirects,
+            substitutions,
+        } => {
+            let mut words: Vec<String> = assignments
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, format_word(value)))
+                .collect();
+            words.extend(argv.iter().map(format_word));
+            let _ = writeln!(
+                text,
+                "{}step {}: {}{}",
+                indent,
+                number,
+                words.join(" "),
+                format_redirects(redirects)
+            );
+            write_substitutions(text, substitutions, depth + 1);
+        }
+        PlanStage::Subshell(body, redirects, substitutions)
+        | PlanStage::Group(body, redirects, substitutions) => {
+            let kind = if let PlanStage::Subshell(..) = stage {
+                "subshell"
+            } else {
+                "group"
+            };
+            let _ = writeln!(text, "{}{}{}", indent, kind, format_redirects(redirects));
+            write_substitutions(text, substitutions, depth + 1);
+            write_text(text, body, depth + 1);
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn json_array<T>(items: &[T], to_json: impl Fn(&T) -> String) -> String {
+    let items: Vec<String> = items.iter().map(to_json).collect();
+    format!("[{}]", items.join(","))
+}
+
+/// The fields of a word: its value, and whether it was expanded.
+fn json_word((value, expanded): &PlanWord) -> String {
+    format!("\"value\":{},\"expanded\":{}", json_string(value), expanded)
+}
+
+fn json_redirects(redirects: &[PlanRedirect]) -> String {
+    json_array(redirects, |redirect| {
+        format!(
+            "{{\"fd\":{},\"op\":{},\"target\":{{{}}}}}",
+            redirect.fd,
+            json_string(redirect.op),
+            json_word(&redirect.target)
+        )
+    })
+}
+
+/// Render a plan as a JSON document.
+pub fn format_json(plan: &Plan) -> String {
+    match plan {
+        Plan::List(plans) => format!(
+            "{{\"type\":\"list\",\"nodes\":{}}}",
+            json_array(plans, format_json)
+        ),
+        Plan::And(left, right) | Plan::Or(left, right) => format!(
+            "{{\"type\":{},\"left\":{},\"right\":{}}}",
+            json_string(if let Plan::And(..) = plan {
+                "and"
+            } else {
+                "or"
+            }),
+            format_json(left),
+            format_json(right)
+        ),
+        Plan::Pipeline { negate, stages } => format!(
+            "{{\"type\":\"pipeline\",\"negate\":{},\"stages\":{}}}",
+            negate,
+            json_array(stages, json_stage)
+        ),
+    }
+}
+
+fn json_stage(stage: &PlanStage) -> String {
+    match stage {
+        PlanStage::Step {
+            number,
+            assignments,
+            argv,
+            redirects,
+            substitutions,
+        } => format!(
+            "{{\"type\":\"step\",\"step\":{},\"assignments\":{},\"argv\":{},\"redirects\":{},\"substitutions\":{}}}",
+            number,
+            json_array(assignments, |(name, value)| format!(
+                "{{\"name\":{},{}}}",
+                json_string(name),
+                json_word(value)
+            )),
+            json_array(argv, |arg| format!("{{{}}}", json_word(arg))),
+            json_redirects(redirects),
+            json_array(substitutions, format_json)
+        ),
+        PlanStage::Subshell(body, redirects, substitutions)
+        | PlanStage::Group(body, redirects, substitutions) => format!(
+            "{{\"type\":{},\"body\":{},\"redirects\":{},\"substitutions\":{}}}",
+            json_string(if let PlanStage::Subshell(..) = stage {
+                "subshell"
+            } else {
+                "group"
+            }),
+            format_json(body),
+            json_redirects(redirects),
+            json_array(substitutions, format_json)
+        ),
+    }
+}
@@ -0,0 +1,119 @@
+use crate::exec::{CmdReturn, Stream};
+use crate::signals;
+use crate::util::random;
+use regex::Regex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A condition for a failed attempt to be retried.
+pub enum RetryOn {
+    /// The command ended with one of these exit codes.
+    Exit(Vec<i32>),
+    Timeout,
+    Stdout(Regex),
+    Stderr(Regex),
+}
+
+impl RetryOn {
+    /// Parse a condition among `exit:CODE[,CODE…]`, `timeout`,
+    /// `stdout:REGEX` and `stderr:REGEX`.
+    pub fn parse(text: &str) -> Result<RetryOn, String> {
+        let (kind, value) = match text.find(':') {
+            Some(i) => (&text[..i], Some(&text[i + 1..])),
+            None => (text, None),
+        };
+        let regex = |value: &str| Regex::new(value).map_err(|error| error.to_string());
+        match (kind, value) {
+            ("exit", Some(value)) => {
+                let codes: Result<Vec<i32>, _> =
+                    value.split(',').map(|code| code.trim().parse()).collect();
+                codes
+                    .map(RetryOn::Exit)
+                    .map_err(|_| format!("invalid exit codes `{}`", value))
+            }
+            ("timeout", None) => Ok(RetryOn::Timeout),
+            ("stdout", Some(value)) => Ok(RetryOn::Stdout(regex(value)?)),
+            ("stderr", Some(value)) => Ok(RetryOn::Stderr(regex(value)?)),
+            _ => Err(format!("unknown retry condition `{}`", text)),
+        }
+    }
+
+    fn matches(&self, run: &CmdReturn) -> bool {
+        match self {
+            RetryOn::Exit(codes) => {
+                run.timed_out.is_none() && run.status.is_some_and(|status| codes.contains(&status))
+            }
+            RetryOn::Timeout => run.timed_out.is_some(),
+            RetryOn::Stdout(regex) => run.output_matches(Stream::Stdout, regex),
+            RetryOn::Stderr(regex) => run.output_matches(Stream::Stderr, regex),
+        }
+    }
+
+    /// The regex this condition looks for in the output, if any.
+    pub fn pattern(&self) -> Option<(Stream, Regex)> {
+        match self {
+            RetryOn::Stdout(regex) => Some((Stream::Stdout, regex.clone())),
+            RetryOn::Stderr(regex) => Some((Stream::Stderr, regex.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// When and how often to run the command again after it failed.
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub delay: Duration,
+    /// What the delay is multiplied by after each retry.
+    pub backoff: f64,
+    /// The longest delay, however many retries it took to grow to it.
+    pub max_delay: Duration,
+    /// The conditions a failed attempt must meet one of to be retried; any
+    /// failed attempt is retried when there is none.
+    pub conditions: Vec<RetryOn>,
+}
+
+impl RetryPolicy {
+    /// Whether a failed attempt is worth retrying. A run interrupted by a
+    /// signal never is.
+    pub fn retryable(&self, run: &CmdReturn) -> bool {
+        run.interrupted.is_none()
+            && (self.conditions.is_empty() || self.conditions.iter().any(|on| on.matches(run)))
+    }
+
+    /// How long to wait after the failed attempt number `attempt`, counting
+    /// from 1. A random jitter of up to a quarter of the delay is added, so
+    /// that jobs failing together do not retry together. The delay is capped
+    /// at `max_delay`, jitter included.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        if self.delay.is_zero() {
+            return Duration::ZERO;
+        }
+        let delay = self.delay.as_secs_f64() * self.backoff.powi(attempt as i32 - 1);
+        Duration::try_from_secs_f64(delay * (1.0 + random() / 4.0))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// Wait for `delay`, unless nrbt receives a signal meanwhile. Returns whether
+/// it waited until the end.
+pub fn wait(delay: Duration) -> bool {
+    let start = Instant::now();
+    while signals::received().is_none() {
+        let elapsed = start.elapsed();
+        if elapsed >= delay {
+            return true;
+        }
+        thread::sleep((delay - elapsed).min(Duration::from_millis(50)));
+    }
+    false
+}
+
+/// How an attempt at running the command ended.
+pub struct Attempt {
+    pub status: Option<i32>,
+    pub signal: Option<i32>,
+    pub timed_out: bool,
+    pub duration: Duration,
+    /// How long nrbt waited before the next attempt, if there was one.
+    pub retried_after: Option<Duration>,
+}
@@ -0,0 +1,49 @@
+use crate::pgroup::signal_current_group;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// The signals nrbt forwards to the pipeline being run instead of dying.
+const FORWARDED: [libc::c_int; 3] = [libc::SIGTERM, libc::SIGINT, libc::SIGHUP];
+
+/// The first forwarded signal nrbt received, or 0.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward(signal: libc::c_int) {
+    // Only async-signal-safe calls here: atomics and kill(2).
+    let _ = RECEIVED.compare_exchange(0, signal, Ordering::SeqCst, Ordering::SeqCst);
+    signal_current_group(signal);
+}
+
+/// Install the handlers forwarding SIGTERM, SIGINT and SIGHUP to the process
+/// group of the pipeline being run, so that nrbt outlives its job and can
+/// still report on it.
+pub fn forward_signals() {
+    for &signal in &FORWARDED {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = forward as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, ptr::null_mut());
+        }
+    }
+}
+
+/// The signal that interrupted the run, if any.
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// The name of a forwarded signal, like `SIGTERM`.
+pub fn signal_name(signal: i32) -> String {
+    match signal {
+        libc::SIGTERM => "SIGTERM".to_string(),
+        libc::SIGINT => "SIGINT".to_string(),
+        libc::SIGHUP => "SIGHUP".to_string(),
+        _ => format!("signal {}", signal),
+    }
+}
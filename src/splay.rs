@@ -0,0 +1,15 @@
+use crate::util::{hostname, random, stable_hash, unit_fraction};
+use std::time::Duration;
+
+/// How long to wait before running `cmd_line`, up to `max`. A stable delay
+/// only depends on the host name and the command line, so that each host
+/// keeps the same slot from one run to the next.
+pub fn splay_delay(max: Duration, stable: bool, cmd_line: &str) -> Duration {
+    let fraction = if stable {
+        let key = format!("{}\n{}", hostname(), cmd_line);
+        unit_fraction(stable_hash(key.as_bytes()))
+    } else {
+        random()
+    };
+    max.mul_f64(fraction)
+}
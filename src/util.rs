@@ -0,0 +1,35 @@
+use std::collections::hash_map::RandomState;
+use std::ffi::CStr;
+use std::hash::{BuildHasher, Hasher};
+
+/// The name of the host, or an empty string when it cannot be known.
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if result != 0 {
+        return String::new();
+    }
+    CStr::from_bytes_until_nul(&buf)
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// A hash of `bytes` that does not change between runs nor builds (64-bit
+/// FNV-1a).
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A random number between 0 and 1.
+pub fn random() -> f64 {
+    // Each `RandomState` is seeded differently.
+    unit_fraction(RandomState::new().build_hasher().finish())
+}
+
+/// Map a 64-bit value evenly to a number between 0 and 1, from its 53 high
+/// bits.
+pub fn unit_fraction(value: u64) -> f64 {
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
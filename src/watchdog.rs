@@ -0,0 +1,77 @@
+use crate::pgroup::signal_current_group;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How a run ended when it timed out.
+pub struct TimedOut {
+    pub after: Duration,
+    /// When SIGKILL had to be sent, the grace period SIGTERM was given.
+    pub killed_after: Option<Duration>,
+}
+
+#[derive(Default)]
+struct State {
+    timed_out: AtomicBool,
+    killed: AtomicBool,
+}
+
+/// Terminates the pipeline being run when the run takes longer than a
+/// timeout: SIGTERM is sent to its process group, then SIGKILL if it is
+/// still running after a grace period.
+pub struct Watchdog {
+    timeout: Duration,
+    kill_after: Duration,
+    state: Arc<State>,
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl Watchdog {
+    pub fn start(timeout: Duration, kill_after: Duration) -> Watchdog {
+        let state = Arc::new(State::default());
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = {
+            let state = Arc::clone(&state);
+            thread::spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(timeout) {
+                    state.timed_out.store(true, Ordering::SeqCst);
+                    signal_current_group(libc::SIGTERM);
+                    if let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(kill_after) {
+                        if signal_current_group(libc::SIGKILL) {
+                            state.killed.store(true, Ordering::SeqCst);
+                        }
+                    }
+                }
+            })
+        };
+
+        Watchdog {
+            timeout,
+            kill_after,
+            state,
+            stop,
+            thread,
+        }
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.state.timed_out.load(Ordering::SeqCst)
+    }
+
+    /// Stop watching, and tell whether the run timed out.
+    pub fn stop(self) -> Option<TimedOut> {
+        let _ = self.stop.send(());
+        let _ = self.thread.join();
+        if !self.state.timed_out.load(Ordering::SeqCst) {
+            return None;
+        }
+        let killed = self.state.killed.load(Ordering::SeqCst);
+        Some(TimedOut {
+            after: self.timeout,
+            killed_after: if killed { Some(self.kill_after) } else { None },
+        })
+    }
+}
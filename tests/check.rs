@@ -0,0 +1,50 @@
+use std::process::{Command, Output};
+
+fn check(cmd_line: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["--check", cmd_line])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn valid_command_line_passes() {
+    let output = check("(cd /srv && make) && echo done > /dev/null");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn check_does_not_run_anything() {
+    let output = check("echo reached");
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn error_is_pointed_at() {
+    let output = check("a ;; b");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "nrbt: missing command before `;` (at byte 3)\n  a ;; b\n     ^\n"
+    );
+}
+
+#[test]
+fn unterminated_quote_points_at_its_start() {
+    let output = check("echo 'a' \"b");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "nrbt: unterminated double quote (at byte 9)\n  echo 'a' \"b\n           ^\n"
+    );
+}
+
+#[test]
+fn error_in_a_later_line_shows_that_line() {
+    let output = check("echo a;\necho b |");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "nrbt: unexpected end of command line after `|` (at byte 16)\n  echo b |\n          ^\n"
+    );
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::{run, run_with};
+
+/// The combined output section of the first step of a report.
+fn combined(report: &str) -> &str {
+    let start = report.find("Combined output\n---------------\n").unwrap();
+    let section = &report[start + "Combined output\n---------------\n".len()..];
+    &section[..section.find("\n\n").unwrap_or(section.len())]
+}
+
+#[test]
+fn streams_are_merged_in_order() {
+    let report = run_with(
+        &["--combined-output"],
+        "sh -c 'echo one; sleep 0.1; echo two >&2; sleep 0.1; echo three'",
+    );
+    assert_eq!(combined(&report), "one\ntwo\nthree");
+}
+
+#[test]
+fn lines_are_timestamped() {
+    let report = run_with(
+        &["--timestamps"],
+        "sh -c 'echo fast; sleep 0.3; printf slow >&2'",
+    );
+    let lines: Vec<&str> = combined(&report).lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("[+0.0"));
+    assert!(lines[0].ends_with("s stdout] fast"));
+    assert!(lines[1].starts_with("[+0.3") || lines[1].starts_with("[+0.4"));
+    assert!(lines[1].ends_with("s stderr] slow"));
+}
+
+#[test]
+fn errors_of_nrbt_are_combined() {
+    let report = run_with(&["--combined-output"], "nrbt-no-such-command");
+    assert_eq!(
+        combined(&report),
+        "nrbt: command not found: nrbt-no-such-command"
+    );
+}
+
+#[test]
+fn no_combined_output_by_default() {
+    assert!(!run("echo one").contains("Combined output"));
+}
@@ -0,0 +1,86 @@
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static RUN_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Run nrbt on `cmd_line` and return the report it wrote in its output file.
+pub fn run(cmd_line: &str) -> String {
+    run_with(&[], cmd_line)
+}
+
+/// Same as `run`, passing `opts` to nrbt before the command line.
+pub fn run_with(opts: &[&str], cmd_line: &str) -> String {
+    run_with_env(opts, &[], cmd_line)
+}
+
+/// Same as `run_with`, adding `vars` to the environment of nrbt.
+pub fn run_with_env(opts: &[&str], vars: &[(&str, &str)], cmd_line: &str) -> String {
+    let mut args = opts.to_vec();
+    args.push(cmd_line);
+    run_args(&args, vars)
+}
+
+/// Run nrbt on `argv`, given after `--`, and return its report.
+pub fn run_argv(argv: &[&str]) -> String {
+    let mut args = vec!["--"];
+    args.extend_from_slice(argv);
+    run_args(&args, &[])
+}
+
+fn run_args(args: &[&str], vars: &[(&str, &str)]) -> String {
+    let output_file = env::temp_dir().join(format!(
+        "nrbt-test-{}-{}",
+        std::process::id(),
+        RUN_ID.fetch_add(1, Ordering::SeqCst)
+    ));
+    let status = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .arg("-o")
+        .arg(&output_file)
+        .args(args)
+        .envs(vars.iter().copied())
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+    let report = fs::read_to_string(&output_file).unwrap();
+    fs::remove_file(&output_file).unwrap();
+    report
+}
+
+/// Run nrbt with `args` as they are, and return how it ended.
+pub fn nrbt(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// A path in the temporary directory for the test `name`, with nothing left
+/// there by a previous run.
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("nrbt-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+/// An empty directory for the test `name`.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = temp_path(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    dir
+}
+
+/// Extract the stdout captured for every step of a report.
+pub fn stdout(report: &str) -> String {
+    report
+        .split("\nStdout\n------\n")
+        .skip(1)
+        .map(|section| &section[..section.find("\nStderr\n------\n").unwrap()])
+        .collect()
+}
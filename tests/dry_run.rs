@@ -0,0 +1,105 @@
+mod common;
+
+use common::run;
+use std::env;
+use std::process::Command;
+
+fn dry_run(opt: &str, cmd_line: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args([opt, cmd_line])
+        .env("NRBT_TEST_VAR", "a b")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn dry_run_prints_the_tree() {
+    let plan = dry_run(
+        "--dry-run",
+        "(cd / && ls $NRBT_TEST_VAR) || ls | wc -l 2>&1 && ! echo \"$(date)\" >> /tmp/log",
+    );
+    assert_eq!(
+        plan,
+        "Plan of command: \"(cd / && ls $NRBT_TEST_VAR) || ls | wc -l 2>&1 && ! echo \"$(date)\" >> /tmp/log\"\n\
+         \n\
+         and\n\
+         \x20 or\n\
+         \x20   subshell\n\
+         \x20     and\n\
+         \x20       step 1: cd /\n\
+         \x20       step 2: ls a b\n\
+         \x20   pipeline\n\
+         \x20     step 3: ls\n\
+         \x20     step 4: wc -l 2>&1\n\
+         \x20 pipeline (negated)\n\
+         \x20   step 6: echo \"$(date)\" >> /tmp/log\n\
+         \x20     substitution\n\
+         \x20       step 5: date\n"
+    );
+}
+
+#[test]
+fn dry_run_does_not_run_anything() {
+    let path = env::temp_dir().join(format!("nrbt-dry-run-{}", std::process::id()));
+    dry_run("--dry-run", &format!("touch {}", path.display()));
+    assert!(!path.exists());
+}
+
+#[test]
+fn dry_run_prints_json() {
+    let plan = dry_run("--dry-run=json", "A=1 grep \"x\\\"y\" < in; (true)");
+    assert_eq!(
+        plan,
+        "{\"type\":\"list\",\"nodes\":[\
+         {\"type\":\"pipeline\",\"negate\":false,\"stages\":[{\"type\":\"step\",\"step\":1,\
+         \"assignments\":[{\"name\":\"A\",\"value\":\"1\",\"expanded\":true}],\
+         \"argv\":[{\"value\":\"grep\",\"expanded\":true},{\"value\":\"x\\\"y\",\"expanded\":true}],\
+         \"redirects\":[{\"fd\":0,\"op\":\"<\",\"target\":{\"value\":\"in\",\"expanded\":true}}],\
+         \"substitutions\":[]}]},\
+         {\"type\":\"pipeline\",\"negate\":false,\"stages\":[{\"type\":\"subshell\",\"body\":\
+         {\"type\":\"pipeline\",\"negate\":false,\"stages\":[{\"type\":\"step\",\"step\":2,\
+         \"assignments\":[],\"argv\":[{\"value\":\"true\",\"expanded\":true}],\"redirects\":[],\
+         \"substitutions\":[]}]},\"redirects\":[],\"substitutions\":[]}]}]}\n"
+    );
+}
+
+#[test]
+fn dry_run_shows_words_kept_as_written_in_json() {
+    let plan = dry_run("--dry-run=json", "echo $(date)");
+    assert!(plan.contains(
+        "\"argv\":[{\"value\":\"echo\",\"expanded\":true},\
+         {\"value\":\"$(date)\",\"expanded\":false}]"
+    ));
+}
+
+#[test]
+fn dry_run_numbers_steps_like_the_report() {
+    let cmd_line = "echo a$(echo b) | cat $(echo /dev/null); echo \"$(true; echo $(echo c))\"";
+    let plan = dry_run("--dry-run", cmd_line);
+    let mut planned: Vec<&str> = plan
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("step "))
+        .collect();
+    planned.sort_by_key(|step| step[..step.find(':').unwrap()].parse::<usize>().unwrap());
+    assert_eq!(
+        planned,
+        [
+            "1: echo b",
+            "2: echo /dev/null",
+            "3: echo a$(echo b)",
+            "4: cat $(echo /dev/null)",
+            "5: true",
+            "6: echo c",
+            "7: echo $(echo c)",
+            "8: echo \"$(true; echo $(echo c))\"",
+        ]
+    );
+
+    let report = run(cmd_line);
+    assert!(report.contains("Step 2 (substitution): echo /dev/null\n"));
+    assert!(report.contains("Step 4: cat /dev/null\n"));
+    assert!(report.contains("Step 7 (substitution): echo c\n"));
+    assert!(report.contains("Step 8: echo c\n"));
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use common::{run_argv, stdout};
+use std::process::Command;
+
+#[test]
+fn argv_after_double_dash_is_not_parsed() {
+    let report = run_argv(&["printf", "%s|", "a b", "*", "$HOME", "a;b", ""]);
+    assert!(report.contains("Exit code: 0"));
+    assert_eq!(stdout(&report), "a b|*|$HOME|a;b||");
+}
+
+#[test]
+fn extra_free_arguments_are_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["echo a", "echo b"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "nrbt: unexpected argument `echo b`\n"
+    );
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::{run, run_with_env, stdout};
+
+#[test]
+fn variables_are_expanded_from_the_environment() {
+    let vars = [("NRBT_DIR", "/srv/app"), ("NRBT_DATE", "2020-01-01")];
+    let report = run_with_env(&[], &vars, "echo $NRBT_DIR/bin ${NRBT_DATE}.tar");
+    assert_eq!(stdout(&report), "/srv/app/bin 2020-01-01.tar\n");
+}
+
+#[test]
+fn unquoted_variables_are_split() {
+    let vars = [("NRBT_WORDS", " a  b ")];
+    let report = run_with_env(&[], &vars, "printf '[%s]' $NRBT_WORDS \"$NRBT_WORDS\"");
+    assert_eq!(stdout(&report), "[a][b][ a  b ]");
+}
+
+#[test]
+fn single_quotes_prevent_expansion() {
+    let vars = [("NRBT_VAR", "value")];
+    let report = run_with_env(&[], &vars, "echo '$NRBT_VAR' \\$NRBT_VAR");
+    assert_eq!(stdout(&report), "$NRBT_VAR $NRBT_VAR\n");
+}
+
+#[test]
+fn unset_variables_expand_to_nothing() {
+    let report = run("printf '[%s]' a $NRBT_UNSET \"$NRBT_UNSET\"");
+    assert_eq!(stdout(&report), "[a][]");
+}
+
+#[test]
+fn assignments_only_apply_to_their_command() {
+    let report = run("NRBT_VAR='a b' sh -c 'echo \"$NRBT_VAR\"'; sh -c 'echo \"[$NRBT_VAR]\"'");
+    assert_eq!(stdout(&report), "a b\n[]\n");
+}
+
+#[test]
+fn command_substitution() {
+    let report = run("echo backup-$(echo 2020-01-01).tar \"$(printf 'a  b\\n\\n')\" `echo x`");
+    assert!(report.contains("Stdout\n------\nbackup-2020-01-01.tar a  b x\n"));
+}
+
+#[test]
+fn nested_command_substitution() {
+    let report = run("echo $(echo $(echo inner) outer)");
+    assert!(report.contains("Stdout\n------\ninner outer\n"));
+}
+
+#[test]
+fn failing_substitution_is_reported() {
+    let report = run("echo x$(sh -c 'echo oops >&2; exit 3')");
+    assert!(report.contains("Step 1 (substitution): sh -c 'echo oops >&2; exit 3'\n"));
+    assert!(report.contains("Exit code: 3"));
+    assert!(report.contains("Stderr\n------\noops\n"));
+}
+
+#[test]
+fn unquoted_patterns_match_files() {
+    let dir = std::env::temp_dir().join(format!("nrbt-glob-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in &["a.log", "b.log", "c.txt", ".hidden.log"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+
+    let report = run(&format!(
+        "printf '[%s]' {0}/*.log '{0}/*.log' {0}/*.none",
+        dir.display()
+    ));
+    assert_eq!(
+        stdout(&report),
+        format!(
+            "[{0}/a.log][{0}/b.log][{0}/*.log][{0}/*.none]",
+            dir.display()
+        )
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tilde_is_expanded_when_unquoted() {
+    let vars = [("HOME", "/home/nrbt")];
+    let report = run_with_env(&[], &vars, "printf '[%s]' ~ ~/bin '~' x~");
+    assert_eq!(stdout(&report), "[/home/nrbt][/home/nrbt/bin][~][x~]");
+}
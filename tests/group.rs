@@ -0,0 +1,66 @@
+mod common;
+
+use common::{run, run_with, run_with_env, stdout};
+use std::env;
+use std::fs;
+
+#[test]
+fn subshell_keeps_its_working_directory() {
+    let report = run("(cd / && pwd) && pwd");
+    let cwd = env::current_dir().unwrap();
+    assert!(report.contains("Exit code: 0"));
+    assert_eq!(stdout(&report), format!("/\n{}\n", cwd.display()));
+}
+
+#[test]
+fn group_shares_the_working_directory() {
+    let report = run("{ cd /; pwd; }; pwd");
+    assert_eq!(stdout(&report), "/\n/\n");
+}
+
+#[test]
+fn subshell_keeps_its_variables() {
+    let report = run("A=1; (B=2; echo $A$B); echo \"[$A$B]\"");
+    assert_eq!(stdout(&report), "12\n[1]\n");
+}
+
+#[test]
+fn assignments_are_not_exported() {
+    let report = run("GREETING=hello; echo $GREETING; sh -c 'echo [$GREETING]'");
+    assert_eq!(stdout(&report), "hello\n[]\n");
+}
+
+#[test]
+fn assignments_to_environment_variables_are_exported() {
+    let report = run_with_env(
+        &[],
+        &[("NRBT_GREETING", "hi")],
+        "NRBT_GREETING=hello; sh -c 'echo $NRBT_GREETING'",
+    );
+    assert_eq!(stdout(&report), "hello\n");
+}
+
+#[test]
+fn group_redirection_applies_to_every_command() {
+    let path = env::temp_dir().join(format!("nrbt-group-{}.txt", std::process::id()));
+    let cmd_line = format!("{{ echo a; echo b >&2; }} > {} 2>&1", path.display());
+    let report = run(&cmd_line);
+    let output = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(report.contains("Exit code: 0"));
+    assert_eq!(output, "a\nb\n");
+}
+
+#[test]
+fn group_status_drives_operators() {
+    let report = run_with(&["-e", "1"], "{ true; false; } && echo no || echo yes");
+    assert_eq!(stdout(&report), "yes\n");
+}
+
+#[test]
+fn cd_to_missing_directory_fails() {
+    let report = run_with(&["-e", "1"], "cd /nrbt-no-such-dir && echo reached");
+    assert!(report.contains("Exit code: 1"));
+    assert!(report.contains("nrbt: cd: /nrbt-no-such-dir:"));
+    assert!(!stdout(&report).contains("reached"));
+}
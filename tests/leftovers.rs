@@ -0,0 +1,39 @@
+mod common;
+
+use common::{run, run_with, stdout};
+use std::time::{Duration, Instant};
+
+#[test]
+fn leftovers_are_not_looked_for_by_default() {
+    let report = run("sh -c 'sleep 1.5 >/dev/null 2>&1 & echo started'");
+    assert!(!report.contains("Leftover"));
+}
+
+#[test]
+fn leftovers_are_reported() {
+    let report = run_with(
+        &["--leftovers=report"],
+        "sh -c 'sleep 1.25 >/dev/null 2>&1 & echo started'",
+    );
+    assert!(report.contains("Leftover processes, still running:\n"));
+    assert!(report.contains(" sleep 1.25\n"));
+}
+
+#[test]
+fn leftovers_are_killed() {
+    let start = Instant::now();
+    let report = run_with(&["--leftovers=kill"], "sh -c 'sleep 30 & echo started'");
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(report.contains("Leftover processes, terminated:\n"));
+    assert!(report.contains(" sleep 30\n"));
+}
+
+#[test]
+fn reported_leftovers_holding_the_output_are_not_waited_for() {
+    let start = Instant::now();
+    let report = run_with(&["--leftovers=report"], "sh -c 'sleep 5 & echo started'");
+    assert!(start.elapsed() < Duration::from_secs(3));
+    assert!(report.contains("Leftover processes, still running:\n"));
+    assert!(report.contains(" sleep 5\n"));
+    assert!(stdout(&report).contains("started\n"));
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::temp_path;
+use std::fs;
+use std::path::Path;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+/// Start nrbt on `cmd_line` writing its output file at `path`, and let it run
+/// for a while.
+fn start(path: &Path, cmd_line: &str) -> Child {
+    let child = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .arg("-o")
+        .arg(path)
+        .arg(cmd_line)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(300));
+    child
+}
+
+#[test]
+fn output_is_written_as_it_arrives() {
+    let path = temp_path("log-live");
+    let mut nrbt = start(
+        &path,
+        "sh -c 'echo early; echo oops >&2; sleep 1; echo late'",
+    );
+    let log = fs::read_to_string(&path).unwrap();
+    assert!(log.starts_with("Log of command: \"sh -c 'echo early; "));
+    assert!(log.contains(&format!("\nPID: {}\n", nrbt.id())));
+    assert!(log.contains("\nStatus: running, this log is incomplete\n"));
+    assert!(log.contains("s stdout] early\n"));
+    assert!(log.contains("s stderr] oops\n"));
+    assert!(!log.contains("s stdout] late"));
+
+    assert!(nrbt.wait().unwrap().success());
+    let log = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(log.contains("\nStatus: complete "));
+    assert!(log.contains("\n\nRun of command: "));
+    assert!(log.contains("\nStdout\n------\nearly\nlate\n"));
+    assert!(!log.contains("s stdout] early\n"));
+}
+
+#[test]
+fn log_of_a_killed_run_stays_incomplete() {
+    let path = temp_path("log-killed");
+    let mut nrbt = start(&path, "sh -c 'echo started; sleep 10'");
+    nrbt.kill().unwrap();
+    nrbt.wait().unwrap();
+    let log = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(log.contains("\nStatus: running, this log is incomplete\n"));
+    assert!(log.contains("s stdout] started\n"));
+    assert!(!log.contains("Run of command: "));
+}
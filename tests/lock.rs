@@ -0,0 +1,163 @@
+mod common;
+
+use common::{nrbt, temp_path, test_dir};
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Start a run of nrbt holding the lock at `path` while it sleeps.
+fn hold_lock(path: &Path, seconds: &str) -> Child {
+    let child = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .arg(format!("--lock={}", path.display()))
+        .arg(format!("sleep {}", seconds))
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(300));
+    child
+}
+
+#[test]
+fn busy_lock_skips_silently() {
+    let path = temp_path("lock-skip");
+    let mut holder = hold_lock(&path, "1");
+    let lock = format!("--lock={}", path.display());
+    let output = nrbt(&[&lock, "sh -c 'echo ran; exit 1'"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    holder.wait().unwrap();
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn busy_lock_is_reported() {
+    let path = temp_path("lock-report");
+    let mut holder = hold_lock(&path, "1");
+    let lock = format!("--lock={}", path.display());
+    let output = nrbt(&[&lock, "--lock-policy", "report", "echo ran"]);
+    assert!(output.status.success());
+    let expected = format!(
+        "\nNot run: lock {} is held by process {}\n",
+        path.display(),
+        holder.id()
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&expected));
+    holder.wait().unwrap();
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn busy_lock_is_waited_for() {
+    let path = temp_path("lock-wait");
+    let mut holder = hold_lock(&path, "0.6");
+    let lock = format!("--lock={}", path.display());
+    let output = nrbt(&[&lock, "--lock-policy", "wait:0.1", "echo ran"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains(", after waiting 0.1"));
+
+    let start = Instant::now();
+    let output = nrbt(&[&lock, "--lock-policy", "wait", "sh -c 'echo ran; exit 1'"]);
+    assert!(output.status.success());
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\nran\n"));
+    holder.wait().unwrap();
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn older_run_is_killed() {
+    let path = temp_path("lock-kill");
+    let holder = hold_lock(&path, "10");
+    let lock = format!("--lock={}", path.display());
+    let start = Instant::now();
+    let output = nrbt(&[&lock, "--lock-policy", "kill", "sh -c 'echo ran; exit 1'"]);
+    assert!(output.status.success());
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\nran\n"));
+    let holder = holder.wait_with_output().unwrap();
+    assert!(String::from_utf8_lossy(&holder.stdout).contains("Interrupted by SIGTERM"));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn lock_file_of_a_dead_run_is_taken() {
+    let path = temp_path("lock-dead");
+    fs::write(&path, "999999999\n").unwrap();
+    let lock = format!("--lock={}", path.display());
+    let output = nrbt(&[&lock, "--lock-policy", "report", "sh -c 'echo ran; exit 1'"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\nran\n"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn held_lock_is_never_taken_over() {
+    let path = temp_path("lock-held");
+    // A process holding the lock without being the run recorded in it.
+    let mut holder = Command::new("flock")
+        .arg(&path)
+        .args(["sleep", "1"])
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(300));
+    fs::write(&path, "999999999\n").unwrap();
+    let lock = format!("--lock={}", path.display());
+    let output = nrbt(&[&lock, "--lock-policy", "report", "echo ran"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("is held by process 999999999"));
+    assert!(!stdout.contains("\nran\n"));
+    holder.wait().unwrap();
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn symbolic_link_is_not_followed() {
+    let path = temp_path("lock-symlink");
+    let target = temp_path("lock-symlink-target");
+    fs::write(&target, "precious\n").unwrap();
+    symlink(&target, &path).unwrap();
+    let lock = format!("--lock={}", path.display());
+    let output = nrbt(&[&lock, "echo ran"]);
+    assert!(!output.status.success());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "precious\n");
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&target).unwrap();
+}
+
+#[test]
+fn default_lock_is_in_the_runtime_directory() {
+    let dir = test_dir("lock-runtime");
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .arg("--lock")
+        .arg("echo ran")
+        .env("XDG_RUNTIME_DIR", &dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn default_lock_in_the_temporary_directory_is_per_user() {
+    let dir = test_dir("lock-tmp");
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .arg("--lock")
+        .arg("echo ran")
+        .env("XDG_RUNTIME_DIR", "")
+        .env("TMPDIR", &dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let entries: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(entries.len(), 1);
+    let name = entries[0].as_ref().unwrap().file_name();
+    let prefix = format!("nrbt-{}-", unsafe { libc::geteuid() });
+    assert!(name.to_string_lossy().starts_with(&prefix));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::{run_with, stdout};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+#[test]
+fn start_and_end_bytes_are_kept() {
+    let report = run_with(&["--max-output", "4"], "printf 0123456789abcdef");
+    assert_eq!(
+        stdout(&report),
+        "0123\n[nrbt: 8 bytes, 0 lines dropped]\ncdef"
+    );
+}
+
+#[test]
+fn start_and_end_lines_are_kept() {
+    let report = run_with(&["--max-output", "2l"], "seq 1 10");
+    assert_eq!(
+        stdout(&report),
+        "1\n2\n[nrbt: 12 bytes, 6 lines dropped]\n9\n10\n"
+    );
+}
+
+#[test]
+fn short_output_is_kept_whole() {
+    let report = run_with(&["--max-output", "1k"], "seq 1 3");
+    assert_eq!(stdout(&report), "1\n2\n3\n");
+}
+
+#[test]
+fn dropped_output_is_matched() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["--max-output", "2l", "-r", "^needle$"])
+        .arg("sh -c 'seq 1 10 >&2; echo needle >&2; seq 1 10 >&2'")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn errors_of_nrbt_are_matched() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["--max-output", "1k", "-r", "not found"])
+        .arg("nosuchcmd; cd /nonexistent")
+        .output()
+        .unwrap();
+    assert!(output.stdout.is_empty());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["--max-output", "1k", "-r", "cd: "])
+        .arg("echo $(cd /nonexistent)")
+        .output()
+        .unwrap();
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn dropped_output_is_spilled() {
+    let report = run_with(&["--max-output", "2l", "--spill-output"], "seq 1 10");
+    let start = report.find(", full output in ").unwrap() + ", full output in ".len();
+    let path = &report[start..start + report[start..].find(']').unwrap()];
+    let mode = fs::metadata(path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let full = fs::read_to_string(path).unwrap();
+    fs::remove_file(path).unwrap();
+    assert_eq!(full, "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+}
+
+#[test]
+fn invalid_size_is_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["--max-output", "10x", "true"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "nrbt: invalid size `10x` for --max-output\n"
+    );
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use common::{run, run_with, stdout};
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+#[test]
+fn and_runs_next_on_success() {
+    let report = run("true && echo reached");
+    assert!(report.contains("Exit code: 0"));
+    assert!(stdout(&report).contains("reached"));
+}
+
+#[test]
+fn and_stops_on_any_non_zero_exit() {
+    let report = run("sh -c 'exit 2' && echo reached");
+    assert!(report.contains("Exit code: 2"));
+    assert!(!stdout(&report).contains("reached"));
+}
+
+#[test]
+fn and_stops_on_command_not_found() {
+    let report = run("nrbt-no-such-command && echo reached");
+    assert!(report.contains("Exit code: 127"));
+    assert!(!stdout(&report).contains("reached"));
+}
+
+#[test]
+fn and_stops_on_signal() {
+    let report = run("sh -c 'kill -9 $$' && echo reached");
+    assert!(report.contains("Terminated by signal: 9"));
+    assert!(!stdout(&report).contains("reached"));
+}
+
+#[test]
+fn or_runs_next_on_failure() {
+    let report = run("sh -c 'exit 3' || echo fallback");
+    assert!(report.contains("Exit code: 0"));
+    assert!(stdout(&report).contains("fallback"));
+}
+
+#[test]
+fn or_runs_next_on_signal() {
+    let report = run("sh -c 'kill -9 $$' || echo fallback");
+    assert!(report.contains("Exit code: 0"));
+    assert!(stdout(&report).contains("fallback"));
+}
+
+#[test]
+fn or_skips_next_on_success() {
+    let report = run("true || echo reached");
+    assert!(report.contains("Exit code: 0"));
+    assert!(!stdout(&report).contains("reached"));
+}
+
+#[test]
+fn and_or_chain_uses_last_executed_status() {
+    let report = run("false && echo first || echo second");
+    assert!(!stdout(&report).contains("first"));
+    assert!(stdout(&report).contains("second"));
+}
+
+#[test]
+fn semicolon_always_runs_next() {
+    let report = run("false; echo reached");
+    assert!(report.contains("Exit code: 0"));
+    assert!(stdout(&report).contains("reached"));
+}
+
+#[test]
+fn pipe_feeds_next_command() {
+    let report = run("printf 'a\\nb\\n' | grep b");
+    assert!(report.contains("Exit code: 0"));
+    assert!(report.contains("Stdout\n------\n(piped to the next command)\n"));
+    assert!(report.contains("Stdout\n------\nb\n"));
+}
+
+#[test]
+fn pipe_status_is_last_stage_status() {
+    let report = run("sh -c 'exit 3' | true");
+    assert!(report.contains("Exit code: 0\n\nDuration"));
+}
+
+#[test]
+fn pipefail_uses_last_failed_stage_status() {
+    let report = run_with(&["--pipefail"], "sh -c 'exit 3' | sh -c 'exit 4' | true");
+    assert!(report.contains("Exit code: 4\n\nDuration"));
+}
+
+#[test]
+fn pipe_captures_stderr_of_every_stage() {
+    let report = run("sh -c 'echo first >&2' | sh -c 'cat; echo second >&2'");
+    assert!(report.contains("Stderr\n------\nfirst\n"));
+    assert!(report.contains("Stderr\n------\nsecond\n"));
+}
+
+#[test]
+fn skipped_pipeline_is_not_run() {
+    let report = run("false && echo reached | cat");
+    assert!(report.contains("Exit code: 1"));
+    assert!(!stdout(&report).contains("reached"));
+}
+
+#[test]
+fn negation_inverts_status() {
+    let report = run("! false && echo reached");
+    assert!(report.contains("Exit code: 0"));
+    assert!(stdout(&report).contains("reached"));
+
+    let report = run("! true");
+    assert!(report.contains("Exit code: 1"));
+}
+
+#[test]
+fn pipeline_goes_on_when_a_stage_cannot_be_started() {
+    let script = env::temp_dir().join(format!("nrbt-test-noexec-{}", std::process::id()));
+    fs::write(&script, "echo never\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let report = run(&format!("echo before | {}; echo after", script.display()));
+    fs::remove_file(&script).unwrap();
+    assert!(report.contains("Exit code: 126"));
+    assert!(report.contains("Exec format error"));
+    assert!(stdout(&report).contains("after"));
+}
+
+#[test]
+fn or_chain_runs_until_a_command_succeeds() {
+    let report = run("false || sh -c 'exit 2' || echo third || echo fourth");
+    assert!(report.contains("Exit code: 0\n\nDuration"));
+    assert_eq!(stdout(&report), "third\n");
+}
+
+#[test]
+fn negation_applies_to_the_whole_pipeline() {
+    let report = run("! false | true");
+    assert!(report.contains("Exit code: 1\n\nDuration"));
+}
+
+#[test]
+fn negated_signal_is_a_success() {
+    let report = run("! sh -c 'kill -9 $$' && echo reached");
+    assert!(report.contains("Exit code: 0\n\nDuration"));
+    assert!(stdout(&report).contains("reached"));
+}
+
+#[test]
+fn quoted_operators_are_not_operators() {
+    let report = run("echo '!' '||' x");
+    assert_eq!(stdout(&report), "! || x\n");
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use chrono::prelude::*;
+use common::{nrbt, test_dir};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn placeholders_are_expanded() {
+    let dir = test_dir("output-placeholders");
+    let template = dir.join("job-%Y-{pid}.log");
+    let child = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .arg("-o")
+        .arg(&template)
+        .arg("true")
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+    assert!(child.wait_with_output().unwrap().status.success());
+    let expected = dir.join(format!("job-{}-{}.log", Local::now().year(), pid));
+    assert!(fs::read_to_string(expected)
+        .unwrap()
+        .contains("Run of command: \"true\""));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_placeholder_is_rejected() {
+    let output = nrbt(&["-o", "job-%Q.log", "true"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "nrbt: -o: invalid placeholder in `job-%Q.log`\n"
+    );
+}
+
+#[test]
+fn runs_are_appended() {
+    let dir = test_dir("output-append");
+    let path = dir.join("job.log");
+    let path = path.to_str().unwrap();
+    assert!(nrbt(&["-o", path, "--append", "echo first"])
+        .status
+        .success());
+    assert!(nrbt(&["-o", path, "--append", "echo second"])
+        .status
+        .success());
+    let log = fs::read_to_string(path).unwrap();
+    assert_eq!(log.matches("\nStatus: complete ").count(), 2);
+    let first = log.find("Run of command: \"echo first\"").unwrap();
+    assert!(log[first..].contains("Run of command: \"echo second\""));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn file_is_replaced_atomically() {
+    let dir = test_dir("output-atomic");
+    let path = dir.join("job.log");
+    fs::write(&path, "previous run\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .arg("-o")
+        .arg(&path)
+        .args(["--atomic", "sh -c 'echo started; sleep 0.6'"])
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "previous run\n");
+    assert!(child.wait().unwrap().success());
+    assert!(fs::read_to_string(&path)
+        .unwrap()
+        .contains("\nStatus: complete "));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn overlapping_appends_are_all_kept() {
+    let dir = test_dir("output-overlap");
+    let path = dir.join("job.log");
+    let mut first = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .arg("-o")
+        .arg(&path)
+        .args(["--append", "sh -c 'echo first; sleep 0.6'"])
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(300));
+    let path = path.to_str().unwrap();
+    assert!(nrbt(&["-o", path, "--append", "echo second"])
+        .status
+        .success());
+    assert!(first.wait().unwrap().success());
+    let log = fs::read_to_string(path).unwrap();
+    assert_eq!(log.matches("\nStatus: complete ").count(), 2);
+    assert!(log.contains("Run of command: \"sh -c 'echo first; sleep 0.6'\""));
+    assert!(log.contains("Run of command: \"echo second\""));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn atomic_replacement_keeps_the_permissions() {
+    let dir = test_dir("output-atomic-mode");
+    let path = dir.join("job.log");
+    fs::write(&path, "previous run\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+    let path = path.to_str().unwrap();
+    assert!(nrbt(&["-o", path, "--atomic", "true"]).status.success());
+    let mode = fs::metadata(path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o7777, 0o600);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mode_is_applied() {
+    let dir = test_dir("output-mode");
+    let path = dir.join("job.log");
+    let path = path.to_str().unwrap();
+    assert!(nrbt(&["-o", path, "--output-mode", "0640", "true"])
+        .status
+        .success());
+    let mode = fs::metadata(path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o7777, 0o640);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{nrbt, run, stdout};
+
+#[test]
+fn quoted_operators_are_arguments() {
+    let report = run("printf '%s\\n' 'a|b' \"c;d\" 'e&&f'");
+    assert!(!report.contains("Step 2"));
+    assert_eq!(stdout(&report), "a|b\nc;d\ne&&f\n");
+}
+
+#[test]
+fn escaped_characters_are_literal() {
+    let report = run("printf '%s\\n' a\\|b c\\;d e\\ f");
+    assert_eq!(stdout(&report), "a|b\nc;d\ne f\n");
+}
+
+#[test]
+fn quotes_can_be_combined_in_a_word() {
+    let report = run("printf '%s\\n' 'it'\\''s' \"say \\\"hi\\\"\" a'b'\"c\"");
+    assert_eq!(stdout(&report), "it's\nsay \"hi\"\nabc\n");
+}
+
+#[test]
+fn empty_quotes_are_an_argument() {
+    let report = run("printf '[%s]\\n' '' \"\"");
+    assert_eq!(stdout(&report), "[]\n[]\n");
+}
+
+#[test]
+fn unterminated_quote_is_rejected() {
+    let output = nrbt(&["echo 'a"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("nrbt: unterminated single quote (at byte 5)\n"));
+
+    let output = nrbt(&["echo \"a"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("nrbt: unterminated double quote"));
+}
+
+#[test]
+fn trailing_backslash_is_rejected() {
+    let output = nrbt(&["echo a\\"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("nrbt: backslash at end of command line"));
+}
+
+#[test]
+fn newlines_separate_commands() {
+    let report = run("echo one\necho two &&\n  echo three\n\n");
+    assert!(report.contains("Step 3: echo three\n"));
+    assert_eq!(stdout(&report), "one\ntwo\nthree\n");
+
+    let report = run("{\n  echo one\n  echo two\n}");
+    assert_eq!(stdout(&report), "one\ntwo\n");
+}
+
+#[test]
+fn quoted_newlines_are_kept() {
+    let report = run("printf '%s|' 'a\nb' \"c\nd\"");
+    assert_eq!(stdout(&report), "a\nb|c\nd|");
+}
+
+#[test]
+fn comments_are_ignored() {
+    let report = run("echo one # not an argument\necho two#three '#four' \\#five");
+    assert_eq!(stdout(&report), "one\ntwo#three #four #five\n");
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::{run, stdout};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("nrbt-redirect-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn output_and_append() {
+    let path = temp_path("append");
+    let report = run(&format!("echo one > {0}; echo two >> {0}", path.display()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+    assert!(report.contains(&format!("(redirected to {}, not captured)", path.display())));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn input() {
+    let path = temp_path("input");
+    fs::write(&path, "from file\n").unwrap();
+    let report = run(&format!("cat < {}", path.display()));
+    assert_eq!(stdout(&report), "from file\n");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn missing_input_fails_the_step() {
+    let report = run("cat < /nonexistent/nrbt && echo reached");
+    assert!(report.contains("nrbt: cannot open /nonexistent/nrbt"));
+    assert!(!stdout(&report).contains("reached"));
+}
+
+#[test]
+fn stderr_to_stdout() {
+    let report = run("sh -c 'echo err >&2' 2>&1");
+    assert_eq!(stdout(&report), "err\n");
+    assert!(report.contains("Stderr\n------\n(redirected to stdout)\n"));
+}
+
+#[test]
+fn duplication_order_matters() {
+    let path = temp_path("order");
+    let report = run(&format!(
+        "sh -c 'echo out; echo err >&2' 2>&1 > {}",
+        path.display()
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "out\n");
+    assert!(stdout(&report).contains("err\n"));
+    fs::remove_file(&path).unwrap();
+}
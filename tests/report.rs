@@ -0,0 +1,19 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn each_step_has_its_own_section() {
+    let report = run("echo first; sh -c 'echo second >&2; exit 2'");
+    assert!(report.contains("Exit code: 2"));
+
+    let step1 = report.find("Step 1: echo first\n").unwrap();
+    let step2 = report
+        .find("Step 2: sh -c 'echo second >&2; exit 2'\n")
+        .unwrap();
+    assert!(step1 < step2);
+    assert!(report[step1..step2].contains("Exit code: 0"));
+    assert!(report[step1..step2].contains("first\n"));
+    assert!(report[step2..].contains("Exit code: 2"));
+    assert!(report[step2..].contains("Stderr\n------\nsecond\n"));
+}
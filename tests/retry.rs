@@ -0,0 +1,146 @@
+mod common;
+
+use common::run_with;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// A command line failing until it ran `runs` times, counting its runs in a
+/// file named after `name`.
+fn flaky(name: &str, runs: usize) -> (String, PathBuf) {
+    let counter = env::temp_dir().join(format!("nrbt-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_file(&counter);
+    let cmd_line = format!(
+        "sh -c 'echo run >> {0}; test $(wc -l < {0}) -ge {1} || exit 75'",
+        counter.display(),
+        runs
+    );
+    (cmd_line, counter)
+}
+
+fn runs(counter: &PathBuf) -> usize {
+    let count = fs::read_to_string(counter).unwrap().lines().count();
+    fs::remove_file(counter).unwrap();
+    count
+}
+
+#[test]
+fn failed_runs_are_retried_until_success() {
+    let (cmd_line, counter) = flaky("retry-success", 3);
+    let report = run_with(&["--retries", "5", "--retry-delay", "0.05"], &cmd_line);
+    assert_eq!(runs(&counter), 3);
+    assert!(report.contains("\nExit code: 0\n"));
+    assert!(report.contains("Attempts:\n  1: exit code 75, "));
+    assert!(report.contains("\n  3: exit code 0, "));
+}
+
+#[test]
+fn retries_are_limited() {
+    let (cmd_line, counter) = flaky("retry-limit", 10);
+    let report = run_with(&["--retries", "2", "--retry-delay", "0.05"], &cmd_line);
+    assert_eq!(runs(&counter), 3);
+    assert!(report.contains("\nExit code: 75\n"));
+    assert!(report.contains("\n  2: exit code 75, 0."));
+    assert!(!report.contains("\n  4: "));
+}
+
+#[test]
+fn only_matching_failures_are_retried() {
+    let (cmd_line, counter) = flaky("retry-on", 3);
+    let opts = [
+        "--retries",
+        "5",
+        "--retry-delay",
+        "0.05",
+        "--retry-on",
+        "exit:1",
+    ];
+    let report = run_with(&opts, &cmd_line);
+    assert_eq!(runs(&counter), 1);
+    assert!(!report.contains("Attempts:"));
+
+    let (cmd_line, counter) = flaky("retry-on-regex", 3);
+    let cmd_line = format!("{} || sh -c 'echo temporary failure >&2; exit 1'", cmd_line);
+    let opts = [
+        "--retries",
+        "5",
+        "--retry-delay",
+        "0.05",
+        "--retry-on",
+        "stderr:^temporary",
+    ];
+    let report = run_with(&opts, &cmd_line);
+    assert_eq!(runs(&counter), 3);
+    assert!(report.contains("\n  3: exit code 0, "));
+}
+
+#[test]
+fn errors_of_nrbt_are_matched_when_output_is_bounded() {
+    let opts = [
+        "--retries",
+        "2",
+        "--retry-delay",
+        "0.05",
+        "--retry-on",
+        "stderr:not found",
+        "--max-output",
+        "1k",
+    ];
+    let report = run_with(&opts, "nosuchcmd");
+    assert!(report.contains("\n  3: exit code 127, "));
+}
+
+#[test]
+fn successful_runs_are_not_retried() {
+    let (cmd_line, counter) = flaky("retry-none", 1);
+    let report = run_with(&["--retries", "3"], &cmd_line);
+    assert_eq!(runs(&counter), 1);
+    assert!(!report.contains("Attempts:"));
+}
+
+#[test]
+fn huge_backoff_does_not_overflow_the_delay() {
+    let report = run_with(
+        &[
+            "--retries",
+            "5",
+            "--retry-delay",
+            "0",
+            "--retry-backoff",
+            "1e300",
+        ],
+        "false",
+    );
+    assert!(report.contains("\n  6: exit code 1, "));
+}
+
+#[test]
+fn delay_is_capped() {
+    let start = Instant::now();
+    let report = run_with(
+        &[
+            "--retries",
+            "3",
+            "--retry-delay",
+            "0.05",
+            "--retry-backoff",
+            "1e300",
+            "--retry-max-delay",
+            "0.1",
+        ],
+        "false",
+    );
+    assert!(report.contains("\n  4: exit code 1, "));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn infinite_backoff_is_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["--retries", "1", "--retry-backoff", "inf", "false"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
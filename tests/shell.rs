@@ -0,0 +1,23 @@
+mod common;
+
+use common::{run_with, stdout};
+
+#[test]
+fn shell_runs_the_command_line_with_sh() {
+    let report = run_with(&["--shell"], "for i in 1 2; do echo $i; done");
+    assert!(report.contains("Exit code: 0"));
+    assert!(report.contains("Step 1: /bin/sh -c"));
+    assert_eq!(stdout(&report), "1\n2\n");
+}
+
+#[test]
+fn shell_path_can_be_given() {
+    let report = run_with(&["--shell=sh"], "echo $0");
+    assert_eq!(stdout(&report), "sh\n");
+}
+
+#[test]
+fn shell_status_is_reported() {
+    let report = run_with(&["--shell", "-e", "3"], "exit 3");
+    assert!(report.contains("Exit code: 3"));
+}
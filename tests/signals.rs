@@ -0,0 +1,64 @@
+mod common;
+
+use common::stdout;
+use std::env;
+use std::fs;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Run nrbt on `cmd_line`, send it `signal` once the command started, and
+/// return the report it wrote in its output file.
+fn run_interrupted(cmd_line: &str, signal: i32) -> String {
+    let output_file = env::temp_dir().join(format!("nrbt-test-signal-{}", signal));
+    let mut nrbt = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .arg("-o")
+        .arg(&output_file)
+        .arg(cmd_line)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(300));
+    unsafe {
+        libc::kill(nrbt.id() as i32, signal);
+    }
+    assert!(nrbt.wait().unwrap().success());
+    let report = fs::read_to_string(&output_file).unwrap();
+    fs::remove_file(&output_file).unwrap();
+    report
+}
+
+#[test]
+fn sigterm_is_forwarded_and_reported() {
+    let report = run_interrupted("echo before; sleep 10 | cat; echo after", libc::SIGTERM);
+    assert!(report.contains("Interrupted by SIGTERM: forwarded to the running job\n"));
+    assert!(report.contains("Terminated by signal: 15"));
+    assert!(stdout(&report).contains("before"));
+    assert!(!stdout(&report).contains("after"));
+}
+
+#[test]
+fn the_job_handles_the_forwarded_signal() {
+    let report = run_interrupted(
+        "sh -c 'trap \"echo hung up; exit 3\" HUP; sleep 10 & wait'",
+        libc::SIGHUP,
+    );
+    assert!(report.contains("Interrupted by SIGHUP: forwarded to the running job\n"));
+    assert!(report.contains("Exit code: 3"));
+    assert!(stdout(&report).contains("hung up"));
+}
+
+#[test]
+fn interruption_is_reported_even_when_a_regex_matches() {
+    let nrbt = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["-u", "before", "echo before; sleep 10"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(300));
+    unsafe {
+        libc::kill(nrbt.id() as i32, libc::SIGTERM);
+    }
+    let output = nrbt.wait_with_output().unwrap();
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(report.contains("Interrupted by SIGTERM"));
+}
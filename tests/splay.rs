@@ -0,0 +1,42 @@
+mod common;
+
+use common::{run, run_with};
+use std::time::{Duration, Instant};
+
+fn splay(report: &str) -> f64 {
+    let line = report
+        .lines()
+        .find(|line| line.starts_with("Splay: "))
+        .unwrap();
+    line["Splay: ".len()..line.find(" seconds").unwrap()]
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn splay_is_waited_for_and_reported() {
+    let start = Instant::now();
+    let report = run_with(&["--splay", "0.5"], "true");
+    let splay = splay(&report);
+    assert!(splay <= 0.5);
+    assert!(start.elapsed() >= Duration::from_secs_f64(splay));
+    assert!(report.contains(", waited before starting\n"));
+}
+
+#[test]
+fn stable_splay_is_the_same_every_run() {
+    let first = splay(&run_with(
+        &["--splay", "0.3", "--splay-stable"],
+        "echo stable",
+    ));
+    let second = splay(&run_with(
+        &["--splay", "0.3", "--splay-stable"],
+        "echo stable",
+    ));
+    assert_eq!(first, second);
+}
+
+#[test]
+fn no_splay_by_default() {
+    assert!(!run("true").contains("Splay"));
+}
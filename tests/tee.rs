@@ -0,0 +1,35 @@
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[test]
+fn output_is_copied_as_it_is_written() {
+    let start = Instant::now();
+    let mut nrbt = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["--tee", "sh -c 'echo early; sleep 2; echo late'"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(nrbt.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "early\n");
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "late\n");
+    assert!(nrbt.wait().unwrap().success());
+}
+
+#[test]
+fn output_is_still_captured() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["--tee", "echo $(echo substituted) out; echo err >&2; false"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("substituted out\nRun of command: "));
+    assert!(stdout.contains("\nStdout\n------\nsubstituted out\n"));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "err\n");
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::stdout;
+use std::env;
+use std::ffi::CStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Open a pseudo-terminal, returning its master and slave sides.
+fn open_terminal() -> (File, File) {
+    unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        assert!(master >= 0);
+        assert_eq!(libc::grantpt(master), 0);
+        assert_eq!(libc::unlockpt(master), 0);
+        let mut name = [0 as libc::c_char; 64];
+        assert_eq!(libc::ptsname_r(master, name.as_mut_ptr(), name.len()), 0);
+        let name = CStr::from_ptr(name.as_ptr()).to_str().unwrap().to_string();
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(name)
+            .unwrap();
+        (File::from_raw_fd(master), slave)
+    }
+}
+
+#[test]
+fn pipeline_can_read_from_the_terminal() {
+    let (mut master, slave) = open_terminal();
+    let output_file = env::temp_dir().join(format!("nrbt-test-terminal-{}", std::process::id()));
+    let mut command = Command::new(env!("CARGO_BIN_EXE_nrbt"));
+    command
+        .arg("-o")
+        .arg(&output_file)
+        .arg("head -n1")
+        .stdin(slave)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Run nrbt in the foreground of the terminal, like from a shell.
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            libc::ioctl(0, libc::TIOCSCTTY, 0);
+            Ok(())
+        });
+    }
+    let mut nrbt = command.spawn().unwrap();
+    thread::sleep(Duration::from_millis(300));
+    master.write_all(b"hello\n").unwrap();
+
+    let start = Instant::now();
+    while nrbt.try_wait().unwrap().is_none() {
+        if start.elapsed() > Duration::from_secs(5) {
+            nrbt.kill().unwrap();
+            nrbt.wait().unwrap();
+            panic!("the command could not read from the terminal");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let report = fs::read_to_string(&output_file).unwrap();
+    fs::remove_file(&output_file).unwrap();
+    assert_eq!(stdout(&report), "hello\n");
+}
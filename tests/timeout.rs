@@ -0,0 +1,76 @@
+mod common;
+
+use common::{run_with, stdout};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[test]
+fn timeout_terminates_the_command() {
+    let start = Instant::now();
+    let report = run_with(&["--timeout", "0.3"], "sleep 10 | cat; echo after");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(report.contains("Timed out after 0.3s: sent SIGTERM\n"));
+    assert!(report.contains("Terminated by signal: 15"));
+    assert!(!stdout(&report).contains("after"));
+}
+
+#[test]
+fn timeout_escalates_to_sigkill() {
+    let report = run_with(
+        &["--timeout", "0.3", "--kill-after", "0.3"],
+        "sh -c 'trap \"\" TERM; sleep 10'",
+    );
+    assert!(report.contains("Timed out after 0.3s: sent SIGTERM, then SIGKILL 0.3s later"));
+    assert!(report.contains("Terminated by signal: 9"));
+}
+
+#[test]
+fn timeout_is_always_reported() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args([
+            "--timeout",
+            "0.3",
+            "sh -c 'trap \"exit 0\" TERM; sleep 10 & wait'",
+        ])
+        .output()
+        .unwrap();
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(report.contains("Exit code: 0"));
+    assert!(report.contains("Timed out after 0.3s"));
+}
+
+#[test]
+fn timeout_is_reported_even_when_a_regex_matches() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args([
+            "--timeout",
+            "0.3",
+            "-u",
+            "ready",
+            "sh -c 'echo ready; sleep 10'",
+        ])
+        .output()
+        .unwrap();
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(report.contains("Timed out after 0.3s"));
+}
+
+#[test]
+fn command_finishing_in_time_is_not_affected() {
+    let report = run_with(&["--timeout", "10"], "echo done");
+    assert!(!report.contains("Timed out"));
+    assert_eq!(stdout(&report), "done\n");
+}
+
+#[test]
+fn huge_timeout_is_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nrbt"))
+        .args(["--timeout", "1000000000000000000000d", "true"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "nrbt: invalid duration `1000000000000000000000d` for --timeout\n"
+    );
+}